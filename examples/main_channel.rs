@@ -17,9 +17,20 @@ fn main() {
     for msg in &handle {
         print!("{}: ", msg.name);
         match msg.message {
-            OutputMessagePayload::Done(status) => println!("exited with status: {:?}", status),
+            OutputMessagePayload::Done(exit) => println!("{}", exit),
             OutputMessagePayload::Error(e) => println!("errored with message: {}", e),
+            OutputMessagePayload::Stopped(step) => println!("stopped by: {:?}", step),
+            OutputMessagePayload::Restarting {
+                changed: Some(changed),
+                ..
+            } => println!("restart after changes to {:?}", changed),
+            OutputMessagePayload::Restarting { attempt, delay, .. } => {
+                println!("restart #{} in {:?}", attempt, delay)
+            }
+            OutputMessagePayload::TimedOut => println!("timed out"),
+            OutputMessagePayload::Queued => println!("queued"),
             OutputMessagePayload::Start => println!("Started"),
+            OutputMessagePayload::Ready => println!("Ready"),
             OutputMessagePayload::Stdout(_, bytes) => {
                 println!("stdout: {}", String::from_utf8_lossy(&bytes))
             }
@@ -0,0 +1,287 @@
+use super::control;
+use super::dependency;
+use super::kill_barrier;
+use super::run;
+use super::template;
+use super::wait_until_ready;
+use super::writer_api;
+use super::ChannelCommand;
+use super::Command;
+use super::CommandHandle;
+use super::CommandStdin;
+use super::ExitOutcome;
+use super::ExitPolicy;
+use super::ExitResult;
+use super::HandleControl;
+use super::OutputMessage;
+use super::Runner;
+use futures_core::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::thread;
+use tokio::sync::{mpsc, oneshot, watch};
+
+/// A handle to commands running under an async runtime. Requires the `tokio` feature.
+/// Created by [Runner::execute_async].
+///
+/// The handle is a [Stream] of the same [OutputMessage]s that the Channel API yields and supports the same configuration,
+/// from restart policies to readiness probes and message templates (see [AsyncCommandHandle::format_message]).
+/// The commands are supervised by the same engine as the sync APIs, so no async task is ever blocked on a child process.
+/// This doesn't save any threads: the engine runs the same threads as the sync APIs, plus one that forwards the messages.
+/// Like [CommandHandle], dropping the handle kills all commands unless it has been joined or [detached](AsyncCommandHandle::detach).
+/// ## Example
+/// ```
+/// use currant::{ChannelCommand, Command, OutputMessagePayload, Runner, CURRENT_WORKING_DIRECTORY};
+/// use tokio_stream::StreamExt;
+///
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// let mut handle = Runner::new()
+///     .command(ChannelCommand::from_string("test1", "ls -la .", CURRENT_WORKING_DIRECTORY).unwrap())
+///     .command(ChannelCommand::from_string("test2", "ls -la ..", CURRENT_WORKING_DIRECTORY).unwrap())
+///     .execute_async();
+///
+/// while let Some(msg) = handle.next().await {
+///     if let OutputMessagePayload::Stdout(_, bytes) = msg.message {
+///         println!("{}: {}", msg.name, String::from_utf8_lossy(&bytes));
+///     }
+/// }
+///
+/// handle.join().await.unwrap();
+/// # });
+/// ```
+pub struct AsyncCommandHandle {
+    channel: mpsc::UnboundedReceiver<OutputMessage>,
+    result: Option<oneshot::Receiver<thread::Result<Vec<ExitResult>>>>,
+    finished: watch::Receiver<bool>,
+    kill_trigger: kill_barrier::KillBarrier,
+    pids: control::PidTable,
+    tracker: dependency::DependencyTracker,
+    commands: run::CommandSet,
+    exit_policy: ExitPolicy,
+    template_strings: template::TemplateStrings,
+    file_handle_flags: bool,
+}
+
+impl AsyncCommandHandle {
+    fn new(handle: CommandHandle, runner: &Runner<ChannelCommand>) -> Self {
+        let (send, recv) = mpsc::unbounded_channel();
+        let (result_send, result_recv) = oneshot::channel();
+        let (finished_send, finished_recv) = watch::channel(false);
+
+        let CommandHandle {
+            handle: supervisor,
+            channel,
+            kill_trigger,
+            pids,
+            tracker,
+            commands,
+            exit_policy,
+        } = handle;
+
+        // Forwards the messages of the sync channel and then hands over the exit results.
+        // The channel closes once every command thread is done, so joining the supervisor doesn't block for long.
+        thread::spawn(move || {
+            for msg in channel {
+                let _ = send.send(msg);
+            }
+            let result = supervisor.join();
+            let _ = finished_send.send(true);
+            let _ = result_send.send(result);
+        });
+
+        AsyncCommandHandle {
+            channel: recv,
+            result: Some(result_recv),
+            finished: finished_recv,
+            kill_trigger,
+            pids,
+            tracker,
+            commands,
+            exit_policy,
+            template_strings: runner.get_template_strings(),
+            file_handle_flags: runner.to_options().file_handle_flags,
+        }
+    }
+
+    /// Formats `message` as text following the message templates of the [Runner], just like the Writer API does.
+    /// Messages without any text (like [OutputMessagePayload::Ready](crate::OutputMessagePayload::Ready)) format to an empty buffer.
+    pub fn format_message(&self, message: &OutputMessage) -> Vec<u8> {
+        writer_api::format_text(message, &self.template_strings, self.file_handle_flags)
+    }
+
+    /// Wait for all processes to exit.
+    /// Returns a list of exit results from the child commands. See [CommandHandle::join] for more details.
+    pub async fn join(mut self) -> Result<Vec<ExitResult>, String> {
+        let result = match self.result.take() {
+            Some(result) => result,
+            None => return Err("handle has already been detached".to_string()),
+        };
+        result
+            .await
+            .map_err(|_| "Thread panic'ed before exit".to_string())?
+            .map_err(|_| "Thread panic'ed before exit".to_string())
+    }
+
+    /// Wait for all processes to exit and reduce their exit results to a single outcome.
+    /// See [CommandHandle::join_outcome](crate::CommandHandle::join_outcome) for more details.
+    pub async fn join_outcome(self) -> Result<ExitOutcome, String> {
+        let policy = self.exit_policy.clone();
+        self.join().await.map(|results| policy.outcome(&results))
+    }
+
+    /// Kills all children processes and waits until every one of them has exited.
+    /// Each command is stopped according to its [StopSequence](crate::StopSequence).
+    /// Messages that are still buffered can be read from the stream afterwards and [AsyncCommandHandle::join] returns right away.
+    pub async fn kill(&self) {
+        let _ = self.kill_trigger.initiate_kill();
+        let mut finished = self.finished.clone();
+        let _ = finished.wait_for(|finished| *finished).await;
+    }
+
+    /// Let the commands keep running without this handle. See [CommandHandle::detach] for more details.
+    pub fn detach(mut self) {
+        self.result.take();
+    }
+
+    /// gets a handle to a [HandleControl] for the underlying processes. See [CommandHandle::get_signaler] for more details.
+    pub fn get_signaler(&self) -> HandleControl {
+        HandleControl::new(self.pids.clone(), self.kill_trigger.clone())
+    }
+
+    /// Wait until the command named `cmd_name` is ready. See [CommandHandle::wait_until_ready] for more details.
+    /// This has to be called from within a tokio runtime.
+    pub async fn wait_until_ready(&self, cmd_name: &str) -> Result<(), String> {
+        let pids = self.pids.clone();
+        let tracker = self.tracker.clone();
+        let kill_trigger = self.kill_trigger.clone();
+        let cmd_name = cmd_name.to_string();
+        tokio::task::spawn_blocking(move || {
+            wait_until_ready(&pids, &tracker, &kill_trigger, &cmd_name)
+        })
+        .await
+        .map_err(|e| e.to_string())?
+    }
+
+    /// Start another command alongside the running ones. See [CommandHandle::add_command] for more details.
+    pub fn add_command<T: AsRef<ChannelCommand>>(&self, cmd: T) -> Result<(), String> {
+        self.commands.add(cmd.as_ref().get_command().clone(), || {})
+    }
+
+    /// Stop the command named `cmd_name` and remove it from the handle. See [CommandHandle::remove_command] for more details.
+    pub fn remove_command(&self, cmd_name: &str) -> Result<(), String> {
+        self.commands.remove(cmd_name)
+    }
+
+    /// Returns a writer that feeds standard in of the command named `cmd_name`. See [CommandHandle::stdin] for more details.
+    /// Writes block until the command has read enough of the input, so use `spawn_blocking` for large inputs.
+    pub fn stdin(&self, cmd_name: &str) -> Result<CommandStdin, String> {
+        super::command_stdin(&self.pids, cmd_name)
+    }
+}
+
+/// Yields the messages of all commands. Ends once every command is done for good.
+impl Stream for AsyncCommandHandle {
+    type Item = OutputMessage;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.channel.poll_recv(cx)
+    }
+}
+
+impl Drop for AsyncCommandHandle {
+    fn drop(&mut self) {
+        // Unlike the sync handles, the commands aren't waited on here so that dropping never blocks a runtime thread
+        if self.result.is_some() {
+            let _ = self.kill_trigger.initiate_kill();
+        }
+    }
+}
+
+impl Runner<ChannelCommand> {
+    /// Execute the commands and stream their messages asynchronously. Requires the `tokio` feature.
+    /// The `Runner` must be constructed with `ChannelCommand`s. See [AsyncCommandHandle] for more details.
+    pub fn execute_async(&mut self) -> AsyncCommandHandle {
+        AsyncCommandHandle::new(super::run_commands(self), self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ChannelCommand;
+    use crate::Command;
+    use crate::OutputMessagePayload;
+    use crate::ReadinessProbe;
+    use crate::Runner;
+    use crate::CURRENT_WORKING_DIRECTORY;
+    use tokio_stream::StreamExt;
+
+    #[tokio::test]
+    async fn streams_messages() {
+        let mut handle = Runner::new()
+            .command(
+                ChannelCommand::from_argv(
+                    "test",
+                    "sh",
+                    ["-c", "echo one; echo two"],
+                    CURRENT_WORKING_DIRECTORY,
+                )
+                .unwrap(),
+            )
+            .execute_async();
+
+        let mut lines = Vec::new();
+        while let Some(msg) = handle.next().await {
+            if let OutputMessagePayload::Stdout(_, bytes) = msg.message {
+                lines.push(bytes);
+            }
+        }
+        let results = handle.join().await.unwrap();
+
+        assert_eq!(lines, vec![b"one".to_vec(), b"two".to_vec()]);
+        assert!(results[0].status.unwrap().success());
+    }
+
+    #[tokio::test]
+    async fn kill_waits_for_exit() {
+        let handle = Runner::new()
+            .command(
+                ChannelCommand::from_argv(
+                    "server",
+                    "sh",
+                    ["-c", "echo ready; exec sleep 30"],
+                    CURRENT_WORKING_DIRECTORY,
+                )
+                .unwrap()
+                .ready_when(ReadinessProbe::log_line("ready").unwrap()),
+            )
+            .execute_async();
+
+        handle.wait_until_ready("server").await.unwrap();
+        handle.kill().await;
+        let results = handle.join().await.unwrap();
+
+        assert!(!results[0].status.unwrap().success());
+    }
+
+    #[tokio::test]
+    async fn formats_with_templates() {
+        let mut handle = Runner::new()
+            .command(
+                ChannelCommand::from_string("test", "echo hello", CURRENT_WORKING_DIRECTORY)
+                    .unwrap(),
+            )
+            .payload_message_template("[{{name}}]{{handle_flag}} ")
+            .should_show_file_handle(true)
+            .execute_async();
+
+        let mut output = Vec::new();
+        while let Some(msg) = handle.next().await {
+            if let OutputMessagePayload::Stdout(..) = msg.message {
+                output.extend(handle.format_message(&msg));
+            }
+        }
+        handle.join().await.unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), "[test] (o) hello\n");
+    }
+}
@@ -0,0 +1,386 @@
+use clap::Parser;
+use clap::ValueEnum;
+use currant::Color;
+use currant::Command;
+use currant::CommandError;
+use currant::ConsoleCommand;
+use currant::ExitPolicy;
+use currant::Procfile;
+use currant::RestartOptions;
+use currant::Runner;
+use currant::WatchPattern;
+use currant::CURRENT_WORKING_DIRECTORY;
+use nix::sys::signal;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/// The exit code for arguments, config files or Procfiles that can't be used
+const USAGE_ERROR: i32 = 2;
+
+/// How often the signal thread checks whether SIGINT or SIGTERM arrived
+const SIGNAL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Run commands concurrently.
+///
+/// The commands come either from the arguments, from a TOML or YAML config file or from a Procfile.
+/// Exits with 0 if every command succeeded and otherwise with the status of the first command that failed (see --success).
+#[derive(Parser)]
+#[command(name = "currant", version)]
+struct Cli {
+    /// The commands to run, each as a single argument like "npm run watch"
+    #[arg(
+        conflicts_with_all = ["config", "procfile"],
+        required_unless_present_any = ["config", "procfile"]
+    )]
+    commands: Vec<String>,
+
+    /// Names of the commands, separated by commas. Unnamed commands are named after their position, starting at 0
+    #[arg(short, long, value_delimiter = ',', conflicts_with_all = ["config", "procfile"])]
+    names: Vec<String>,
+
+    /// Run every command through `sh -c` so that pipes, variables and other shell features work
+    #[arg(short, long, conflicts_with_all = ["config", "procfile"])]
+    shell: bool,
+
+    /// Load the commands and options from a TOML (.toml) or YAML (.yaml, .yml) config file
+    #[arg(short = 'f', long, conflicts_with = "procfile")]
+    config: Option<PathBuf>,
+
+    /// Load the commands from a Procfile
+    #[arg(short, long)]
+    procfile: Option<PathBuf>,
+
+    /// Load environment variables for the commands of the Procfile from a .env file. Can be given several times
+    #[arg(short, long, requires = "procfile", conflicts_with_all = ["commands", "config"])]
+    env_file: Vec<PathBuf>,
+
+    /// Instance counts of the processes of the Procfile, like web=2,worker=1
+    #[arg(short = 'c', long, requires = "procfile", conflicts_with_all = ["commands", "config"])]
+    concurrency: Option<String>,
+
+    /// What to do when a command fails
+    #[arg(short, long, value_enum)]
+    restart: Option<RestartMode>,
+
+    /// Kill all other commands as soon as one of them fails (the same as --restart kill)
+    #[arg(long, conflicts_with = "restart")]
+    kill_others_on_fail: bool,
+
+    /// Kill all other commands as soon as one of them exits, whether it failed or not (the same as --restart kill-on-exit)
+    #[arg(long, conflicts_with_all = ["restart", "kill_others_on_fail"])]
+    kill_others: bool,
+
+    /// Which commands decide the exit code: all (the first command to fail, if any), first, last or command-<name>
+    #[arg(long, default_value = "all")]
+    success: ExitPolicy,
+
+    /// Kill all other commands as soon as the command that decides the exit code (see --success) is done
+    #[arg(long)]
+    kill_when_decided: bool,
+
+    /// Restart the commands when files matching the glob pattern (like src/**/*.rs) change. Can be given several times.
+    /// Config files set the patterns of each command instead
+    #[arg(short, long, conflicts_with = "config")]
+    watch: Vec<String>,
+
+    /// Milliseconds that watched files have to stay unchanged before the commands are restarted
+    #[arg(long)]
+    watch_debounce: Option<u64>,
+
+    /// Keep watching the files of commands that are done on their own (like a build that failed) and run them again on the next change
+    #[arg(long)]
+    watch_after_exit: bool,
+
+    /// Don't print messages about commands starting
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// Prefix every line with (o) for standard out or (e) for standard error
+    #[arg(long)]
+    handle_flags: bool,
+
+    /// Colors of the commands, separated by commas: color names like red, #rrggbb, random or default.
+    /// Config files set the color of each command instead
+    #[arg(long, value_delimiter = ',', conflicts_with = "config")]
+    colors: Vec<Color>,
+
+    /// Don't color the output of the commands
+    #[arg(long, conflicts_with_all = ["colors", "config"])]
+    no_color: bool,
+
+    /// Template of the message printed when a command starts
+    #[arg(long)]
+    start_template: Option<String>,
+
+    /// Template of the message printed when a command exits
+    #[arg(long)]
+    done_template: Option<String>,
+
+    /// Template of the prefix of every line of output
+    #[arg(long)]
+    payload_template: Option<String>,
+
+    /// Template of the message printed when a command runs into an error
+    #[arg(long)]
+    error_template: Option<String>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum RestartMode {
+    /// Let the failed command die and keep the other commands running
+    Continue,
+    /// Restart the failed command with exponential backoff
+    Restart,
+    /// Kill all other commands
+    Kill,
+    /// Kill all other commands, also when the command exits successfully
+    KillOnExit,
+}
+
+impl From<RestartMode> for RestartOptions {
+    fn from(mode: RestartMode) -> Self {
+        match mode {
+            RestartMode::Continue => RestartOptions::Continue,
+            RestartMode::Restart => RestartOptions::Restart,
+            RestartMode::Kill => RestartOptions::Kill,
+            RestartMode::KillOnExit => RestartOptions::KillOnExit,
+        }
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let mut runner = match build_runner(&cli) {
+        Ok(runner) => runner,
+        Err(e) => {
+            eprintln!("currant: {}", e);
+            process::exit(USAGE_ERROR);
+        }
+    };
+
+    install_signal_handlers();
+    let handle = runner.execute();
+    let signaler = handle.get_signaler();
+    thread::spawn(move || loop {
+        if STOP_REQUESTED.load(Ordering::SeqCst) {
+            let _ = signaler.kill_all();
+            return;
+        }
+        thread::sleep(SIGNAL_POLL_INTERVAL);
+    });
+
+    match handle.join_outcome() {
+        Ok(outcome) => process::exit(outcome.code),
+        Err(e) => {
+            eprintln!("currant: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+fn build_runner(cli: &Cli) -> Result<Runner<ConsoleCommand>, CommandError> {
+    let mut runner = if let Some(path) = &cli.config {
+        Runner::from_config_file(path)?
+    } else {
+        let mut commands = match &cli.procfile {
+            Some(path) => {
+                let mut procfile = Procfile::from_file(path)?;
+                for env_file in &cli.env_file {
+                    procfile.env_file(env_file)?;
+                }
+                if let Some(spec) = &cli.concurrency {
+                    procfile.concurrency_spec(spec)?;
+                }
+                procfile.commands()?
+            }
+            None => command_args(cli)?,
+        };
+
+        let patterns = cli
+            .watch
+            .iter()
+            .map(WatchPattern::new)
+            .collect::<Result<Vec<_>, _>>()?;
+        for (idx, cmd) in commands.iter_mut().enumerate() {
+            for pattern in &patterns {
+                cmd.watch(pattern.clone());
+            }
+            if cli.no_color {
+                cmd.color(Color::Default);
+            } else if let Some(color) = cli.colors.get(idx) {
+                cmd.color(color.clone());
+            }
+        }
+
+        let mut runner = Runner::new();
+        runner.process_groups(true);
+        for cmd in &commands {
+            runner.command(cmd);
+        }
+        runner
+    };
+
+    if let Some(mode) = cli.restart {
+        runner.restart(mode.into());
+    }
+    if cli.kill_others_on_fail {
+        runner.restart(RestartOptions::Kill);
+    }
+    if cli.kill_others {
+        runner.restart(RestartOptions::KillOnExit);
+    }
+    if let Some(debounce) = cli.watch_debounce {
+        runner.watch_debounce(Duration::from_millis(debounce));
+    }
+    if cli.watch_after_exit {
+        runner.watch_after_exit(true);
+    }
+    runner.exit_policy(cli.success.clone());
+    if cli.kill_when_decided {
+        runner.kill_when_decided(true);
+    }
+    if cli.quiet {
+        runner.quiet(true);
+    }
+    if cli.handle_flags {
+        runner.should_show_file_handle(true);
+    }
+    if let Some(template) = &cli.start_template {
+        runner.start_message_template(template);
+    }
+    if let Some(template) = &cli.done_template {
+        runner.done_message_template(template);
+    }
+    if let Some(template) = &cli.payload_template {
+        runner.payload_message_template(template);
+    }
+    if let Some(template) = &cli.error_template {
+        runner.error_message_template(template);
+    }
+    Ok(runner)
+}
+
+/// Builds the commands given as arguments
+fn command_args(cli: &Cli) -> Result<Vec<ConsoleCommand>, CommandError> {
+    let mut commands = Vec::new();
+    for (idx, command_string) in cli.commands.iter().enumerate() {
+        let name = cli
+            .names
+            .get(idx)
+            .cloned()
+            .unwrap_or_else(|| idx.to_string());
+        let cmd = if cli.shell {
+            ConsoleCommand::from_argv(
+                name,
+                "sh",
+                ["-c", command_string],
+                CURRENT_WORKING_DIRECTORY,
+            )?
+        } else {
+            ConsoleCommand::from_string(name, command_string, CURRENT_WORKING_DIRECTORY)?
+        };
+        commands.push(cmd);
+    }
+    Ok(commands)
+}
+
+extern "C" fn request_stop(_: libc::c_int) {
+    STOP_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Commands given on the command line or in a Procfile run in their own process groups (config files choose via `process_groups`),
+/// so a Ctrl-C in the terminal only reaches currant. Instead of dying right away, currant stops the commands with their stop sequences
+/// and exits once they are done.
+fn install_signal_handlers() {
+    let action = signal::SigAction::new(
+        signal::SigHandler::Handler(request_stop),
+        signal::SaFlags::SA_RESTART,
+        signal::SigSet::empty(),
+    );
+    for sig in [signal::Signal::SIGINT, signal::Signal::SIGTERM] {
+        unsafe {
+            let _ = signal::sigaction(sig, &action);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::build_runner;
+    use super::Cli;
+    use clap::CommandFactory;
+    use clap::Parser;
+    use currant::ExitPolicy;
+
+    #[test]
+    fn parses_arguments() {
+        Cli::command().debug_assert();
+
+        let cli = Cli::try_parse_from([
+            "currant",
+            "--names",
+            "web,worker",
+            "--colors",
+            "red,#00ff00",
+            "--kill-others-on-fail",
+            "npm start",
+            "npm run worker",
+        ])
+        .unwrap();
+        assert_eq!(cli.commands, vec!["npm start", "npm run worker"]);
+        assert_eq!(cli.names, vec!["web", "worker"]);
+        assert_eq!(cli.colors.len(), 2);
+
+        assert!(Cli::try_parse_from(["currant"]).is_err());
+        assert!(Cli::try_parse_from(["currant", "--procfile", "Procfile", "ls"]).is_err());
+        assert!(Cli::try_parse_from(["currant", "--env-file", ".env", "ls"]).is_err());
+        assert!(Cli::try_parse_from(["currant", "-f", "stack.toml", "-c", "web=2"]).is_err());
+        assert!(Cli::try_parse_from(["currant", "--kill-others", "-r", "restart", "ls"]).is_err());
+        assert!(Cli::try_parse_from(["currant", "-f", "stack.toml", "-w", "src/*.rs"]).is_err());
+        for ignored in [
+            ["--names", "web"],
+            ["--colors", "red"],
+            ["--no-color", "--quiet"],
+        ] {
+            let mut args = vec!["currant", "-f", "stack.toml"];
+            args.extend(ignored);
+            assert!(Cli::try_parse_from(&args).is_err(), "{:?}", args);
+        }
+        assert!(Cli::try_parse_from(["currant", "-p", "Procfile", "--shell"]).is_err());
+        assert!(Cli::try_parse_from(["currant", "-p", "Procfile", "--colors", "red"]).is_ok());
+        let cli = Cli::try_parse_from(["currant", "-w", "src/[ab", "ls"]).unwrap();
+        assert!(build_runner(&cli).is_err());
+    }
+
+    #[test]
+    fn exits_by_policy() {
+        let cli = Cli::try_parse_from([
+            "currant",
+            "--shell",
+            "--quiet",
+            "--names",
+            "server,tests",
+            "--success",
+            "command-tests",
+            "--kill-when-decided",
+            "sleep 10",
+            "exit 3",
+        ])
+        .unwrap();
+        assert_eq!(cli.success, ExitPolicy::Command("tests".to_string()));
+        assert!(Cli::try_parse_from(["currant", "--success", "most", "ls"]).is_err());
+
+        let outcome = build_runner(&cli)
+            .unwrap()
+            .execute()
+            .join_outcome()
+            .unwrap();
+        assert_eq!(outcome.code, 3);
+        assert_eq!(outcome.name.as_deref(), Some("tests"));
+    }
+}
@@ -0,0 +1,392 @@
+use nix::sys::signal::Signal;
+use std::io;
+use std::process;
+use std::process::ExitStatus;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+use std::time::Instant;
+
+/// How often a running child is checked for exit
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Describes how a command is stopped when it gets killed.
+/// First `signal` is sent to the process. If it is still running after `timeout`, it is killed with `SIGKILL`.
+/// Set for all commands via [Runner::stop_sequence](crate::Runner::stop_sequence) or for a single command via
+/// [Command::stop_sequence](crate::Command::stop_sequence).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StopSequence {
+    /// The signal asking the process to shut down. Defaults to `SIGTERM`.
+    pub signal: Signal,
+    /// How long to wait for the process to exit after `signal` before escalating to `SIGKILL`. Defaults to 5 seconds.
+    pub timeout: Duration,
+}
+
+impl StopSequence {
+    /// Construct a stop sequence sending `signal` and escalating to `SIGKILL` after `timeout`
+    pub fn new(signal: Signal, timeout: Duration) -> Self {
+        StopSequence { signal, timeout }
+    }
+}
+
+impl Default for StopSequence {
+    fn default() -> Self {
+        StopSequence {
+            signal: Signal::SIGTERM,
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// The step of a [StopSequence] that ended a process.
+/// Reported via [OutputMessagePayload::Stopped](crate::OutputMessagePayload::Stopped).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopStep {
+    /// The process exited after receiving the stop signal
+    Signal(Signal),
+    /// The process didn't exit in time and was killed with `SIGKILL`
+    Killed,
+}
+
+struct ChildState {
+    child: process::Child,
+    status: Option<ExitStatus>,
+    stop_step: Option<StopStep>,
+}
+
+/// A child process that can be waited on by one thread while being signalled and stopped from others.
+/// Signals are only ever delivered while the process hasn't been reaped so they can't hit a recycled pid.
+pub struct SharedChild {
+    pid: u32,
+    process_group: bool,
+    state: Mutex<ChildState>,
+    exited: Condvar,
+}
+
+impl SharedChild {
+    /// Wraps a spawned child. `process_group` tells whether the child leads its own process group,
+    /// in which case signals are sent to the whole group.
+    pub fn new(child: process::Child, process_group: bool) -> Self {
+        SharedChild {
+            pid: child.id(),
+            process_group,
+            state: Mutex::new(ChildState {
+                child,
+                status: None,
+                stop_step: None,
+            }),
+            exited: Condvar::new(),
+        }
+    }
+
+    fn lock(&self) -> io::Result<MutexGuard<'_, ChildState>> {
+        self.state
+            .lock()
+            .map_err(|e| io::Error::other(e.to_string()))
+    }
+
+    /// Blocks until the process exits and returns its exit status
+    pub fn wait(&self) -> io::Result<ExitStatus> {
+        let state = self.lock()?;
+        let (_state, status) = self.wait_until(state, None)?;
+        status.ok_or_else(|| io::Error::other("process didn't exit"))
+    }
+
+    /// Polls the process until it exits or `deadline` passes.
+    /// Whichever thread notices the exit first records the status and wakes up all other waiting threads.
+    fn wait_until<'a>(
+        &'a self,
+        mut state: MutexGuard<'a, ChildState>,
+        deadline: Option<Instant>,
+    ) -> io::Result<(MutexGuard<'a, ChildState>, Option<ExitStatus>)> {
+        loop {
+            if let Some(status) = state.status {
+                return Ok((state, Some(status)));
+            }
+
+            if let Some(status) = state.child.try_wait()? {
+                state.status = Some(status);
+                self.exited.notify_all();
+                return Ok((state, Some(status)));
+            }
+
+            let mut poll_interval = EXIT_POLL_INTERVAL;
+            if let Some(deadline) = deadline {
+                let now = Instant::now();
+                if now >= deadline {
+                    return Ok((state, None));
+                }
+                poll_interval = poll_interval.min(deadline - now);
+            }
+
+            state = self
+                .exited
+                .wait_timeout(state, poll_interval)
+                .map_err(|e| io::Error::other(e.to_string()))?
+                .0;
+        }
+    }
+
+    /// Sends `signal` to the process (or its process group).
+    /// Returns an error message if the process has already exited or the signal couldn't be sent
+    pub fn signal(&self, signal: Signal) -> Result<(), String> {
+        let state = self.lock().map_err(|e| e.to_string())?;
+        if state.status.is_some() {
+            return Err(format!("process {} has already exited", self.pid));
+        }
+        self.send_signal(signal)
+    }
+
+    /// Must only be called while holding the state lock of a process that hasn't been reaped
+    fn send_signal(&self, signal: Signal) -> Result<(), String> {
+        let pid = nix::unistd::Pid::from_raw(self.pid as i32);
+        let res = if self.process_group {
+            nix::sys::signal::killpg(pid, signal)
+        } else {
+            nix::sys::signal::kill(pid, signal)
+        };
+        res.map_err(|e| e.to_string())
+    }
+
+    /// Returns true once the process has exited and been reaped
+    pub fn has_exited(&self) -> bool {
+        self.lock()
+            .map(|state| state.status.is_some())
+            .unwrap_or(true)
+    }
+
+    /// Runs the stop sequence: sends the stop signal, waits for the process to exit and escalates to `SIGKILL` on timeout.
+    /// Does nothing if the process has already exited.
+    pub fn stop(&self, sequence: &StopSequence) {
+        let mut state = match self.lock() {
+            Ok(state) => state,
+            Err(_) => return,
+        };
+        if state.status.is_some() || state.stop_step.is_some() {
+            return;
+        }
+
+        state.stop_step = Some(StopStep::Signal(sequence.signal));
+        let _ = self.send_signal(sequence.signal);
+
+        let deadline = Instant::now() + sequence.timeout;
+        if let Ok((mut state, None)) = self.wait_until(state, Some(deadline)) {
+            state.stop_step = Some(StopStep::Killed);
+            if self.process_group {
+                let _ = self.send_signal(Signal::SIGKILL);
+            } else {
+                let _ = state.child.kill();
+            }
+        }
+    }
+
+    /// The step of the stop sequence that was last carried out, if the process was stopped
+    pub fn stop_step(&self) -> Option<StopStep> {
+        self.lock().ok().and_then(|state| state.stop_step)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StopSequence;
+    use super::StopStep;
+    use crate::ChannelCommand;
+    use crate::Command;
+    use crate::OutputMessagePayload;
+    use crate::ReadinessProbe;
+    use crate::Runner;
+    use crate::Signal;
+    use crate::CURRENT_WORKING_DIRECTORY;
+    use std::thread;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    fn stop_with_script(script: &str) -> Vec<StopStep> {
+        let handle = Runner::new()
+            .command(
+                ChannelCommand::from_argv(
+                    "server",
+                    "sh",
+                    ["-c", script],
+                    CURRENT_WORKING_DIRECTORY,
+                )
+                .unwrap()
+                .ready_when(ReadinessProbe::log_line("ready").unwrap()),
+            )
+            .stop_sequence(StopSequence::new(
+                Signal::SIGTERM,
+                Duration::from_millis(300),
+            ))
+            .execute();
+
+        handle.wait_until_ready("server").unwrap();
+        handle.kill();
+
+        let mut steps = Vec::new();
+        for msg in &handle {
+            if let OutputMessagePayload::Stopped(step) = msg.message {
+                steps.push(step);
+            }
+        }
+        handle.join().unwrap();
+        steps
+    }
+
+    #[test]
+    fn stops_with_signal() {
+        let steps =
+            stop_with_script("trap 'exit 0' TERM; echo ready; while true; do sleep 0.05; done");
+        assert_eq!(steps, vec![StopStep::Signal(Signal::SIGTERM)]);
+    }
+
+    #[test]
+    fn escalates_to_kill() {
+        let steps = stop_with_script("trap '' TERM; echo ready; while true; do sleep 0.05; done");
+        assert_eq!(steps, vec![StopStep::Killed]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn kills_grandchildren() {
+        let handle = Runner::new()
+            .command(
+                ChannelCommand::from_argv(
+                    "server",
+                    "sh",
+                    ["-c", "sleep 30 & echo $!; wait"],
+                    CURRENT_WORKING_DIRECTORY,
+                )
+                .unwrap()
+                .ready_when(ReadinessProbe::log_line("^[0-9]+$").unwrap()),
+            )
+            .process_groups(true)
+            .execute();
+
+        handle.wait_until_ready("server").unwrap();
+        let killed_at = Instant::now();
+        handle.kill();
+
+        let mut grandchild = None;
+        for msg in &handle {
+            if let OutputMessagePayload::Stdout(_, bytes) = msg.message {
+                grandchild = String::from_utf8_lossy(&bytes).trim().parse::<u32>().ok();
+            }
+        }
+        handle.join().unwrap();
+        // the grandchild holds on to the output pipe, so the output only ends once it is gone as well
+        assert!(killed_at.elapsed() < Duration::from_secs(5));
+
+        let stat = format!("/proc/{}/stat", grandchild.unwrap());
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !grandchild_gone(&stat) {
+            assert!(Instant::now() < deadline, "grandchild survived the kill");
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    /// Spawns this test again as a helper process that runs a command with `kill_on_parent_death`, kills the helper with
+    /// `SIGKILL` and checks that the command goes down with it
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn commands_die_with_parent() {
+        if let Ok(pid_file) = std::env::var("CURRANT_PID_FILE") {
+            let mut cmd = ChannelCommand::from_argv(
+                "orphan",
+                "sh",
+                ["-c", "echo $$ > \"$CURRANT_PID_FILE\"; exec sleep 30"],
+                CURRENT_WORKING_DIRECTORY,
+            )
+            .unwrap();
+            cmd.env("CURRANT_PID_FILE", pid_file);
+            let _ = Runner::new()
+                .command(cmd)
+                .kill_on_parent_death(true)
+                .execute()
+                .join();
+            return;
+        }
+
+        let pid_file = std::env::temp_dir().join(format!("currant_pid_{}", std::process::id()));
+        let _ = std::fs::remove_file(&pid_file);
+        let mut helper = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "child::tests::commands_die_with_parent"])
+            .env("CURRANT_PID_FILE", &pid_file)
+            .stdout(std::process::Stdio::null())
+            .spawn()
+            .unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let pid = loop {
+            let pid = std::fs::read_to_string(&pid_file)
+                .ok()
+                .and_then(|contents| contents.trim().parse::<u32>().ok());
+            if let Some(pid) = pid {
+                break pid;
+            }
+            assert!(
+                Instant::now() < deadline,
+                "the helper didn't start the command"
+            );
+            thread::sleep(Duration::from_millis(20));
+        };
+        helper.kill().unwrap();
+        helper.wait().unwrap();
+        std::fs::remove_file(&pid_file).unwrap();
+
+        let stat = format!("/proc/{}/stat", pid);
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !grandchild_gone(&stat) {
+            assert!(Instant::now() < deadline, "the command survived its parent");
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn process_groups_are_opt_in() {
+        let process_group = |process_groups| {
+            let mut runner = Runner::new();
+            runner.command(
+                ChannelCommand::from_argv(
+                    "group",
+                    "sh",
+                    ["-c", "echo $$ $(cut -d ' ' -f 5 /proc/$$/stat)"],
+                    CURRENT_WORKING_DIRECTORY,
+                )
+                .unwrap(),
+            );
+            if process_groups {
+                runner.process_groups(true);
+            }
+            let handle = runner.execute();
+            let mut ids = Vec::new();
+            for msg in &handle {
+                if let OutputMessagePayload::Stdout(_, bytes) = msg.message {
+                    ids = String::from_utf8_lossy(&bytes)
+                        .split(' ')
+                        .map(|id| id.parse::<i32>().unwrap())
+                        .collect();
+                }
+            }
+            handle.join().unwrap();
+            (ids[0], ids[1])
+        };
+
+        let (_, pgid) = process_group(false);
+        assert_eq!(pgid, unsafe { libc::getpgrp() });
+        let (pid, pgid) = process_group(true);
+        assert_eq!(pgid, pid);
+    }
+
+    /// The grandchild is gone once its stat file disappears or it is a zombie waiting to be reaped by init
+    #[cfg(target_os = "linux")]
+    fn grandchild_gone(stat: &str) -> bool {
+        match std::fs::read_to_string(stat) {
+            Ok(contents) => contents
+                .rsplit(')')
+                .next()
+                .map(|rest| rest.trim_start().starts_with('Z'))
+                .unwrap_or(false),
+            Err(_) => true,
+        }
+    }
+}
@@ -1,11 +1,12 @@
 use atty::Stream;
 use std::collections::HashMap;
+use std::str::FromStr;
 
 /// Represents colors in an ANSI terminal. Represents the color of the text printed to the screen.
 /// This is used in the Console API to tell `currant` what color to print the command metadata.
 /// Each command should get a different color to visually differentiate output.
 /// A Color can be an RGB value, random, or the terminal's default color.
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug, Default)]
 pub enum Color {
     /// Specify a specifc color using RGB values. Also see the equivalent [Color::rgb] function for the equivalent constructor.
     RGB(u8, u8, u8),
@@ -16,6 +17,7 @@ pub enum Color {
     /// If you wish to have true random colors, you can either manually set RGB values or use the [Color::true_random] function.
     Random,
     /// The default color for your terminal (depends on your current settings).
+    #[default]
     Default,
 }
 
@@ -62,6 +64,35 @@ impl Color {
     }
 }
 
+impl FromStr for Color {
+    type Err = String;
+
+    /// Parses a color name (`red`, `green`, `yellow`, `blue`, `magenta`, `cyan`, `white`, `black`, `random` or `default`)
+    /// or an RGB value written as `#rrggbb`
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "red" => Ok(Color::RED),
+            "green" => Ok(Color::GREEN),
+            "yellow" => Ok(Color::YELLOW),
+            "blue" => Ok(Color::BLUE),
+            "magenta" => Ok(Color::MAGENTA),
+            "cyan" => Ok(Color::CYAN),
+            "white" => Ok(Color::WHITE),
+            "black" => Ok(Color::BLACK),
+            "random" => Ok(Color::Random),
+            "default" => Ok(Color::Default),
+            hex => hex
+                .strip_prefix('#')
+                .filter(|hex| hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .map(|rgb| Color::rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+                .ok_or_else(|| {
+                    format!("invalid color '{}', expected a color name or #rrggbb", name)
+                }),
+        }
+    }
+}
+
 pub fn open_sequence(color: &Color) -> String {
     if atty::is(Stream::Stdout) {
         match color {
@@ -87,12 +118,6 @@ pub fn close_sequence() -> String {
     }
 }
 
-impl Default for Color {
-    fn default() -> Self {
-        Color::Default
-    }
-}
-
 pub fn populate_random_colors(color_list: &mut HashMap<String, Color>) {
     let mut num_random = 0;
 
@@ -111,6 +136,51 @@ pub fn populate_random_colors(color_list: &mut HashMap<String, Color>) {
     }
 }
 
+/// Picks a color whose hue is as far as possible from the hues of the given colors
+pub fn distinct_color<'a, I: IntoIterator<Item = &'a Color>>(colors: I) -> Color {
+    let mut hues: Vec<u32> = colors
+        .into_iter()
+        .filter_map(|color| match color {
+            Color::RGB(r, g, b) => rgb_to_theta(*r, *g, *b),
+            _ => None,
+        })
+        .collect();
+    if hues.is_empty() {
+        return theta_to_rgb(rand::random::<u32>() % 360);
+    }
+    hues.sort_unstable();
+
+    // the widest gap between neighboring hues, wrapping around the color wheel
+    let mut best_start = hues[hues.len() - 1];
+    let mut best_gap = hues[0] + 360 - best_start;
+    for pair in hues.windows(2) {
+        if pair[1] - pair[0] > best_gap {
+            best_start = pair[0];
+            best_gap = pair[1] - pair[0];
+        }
+    }
+    theta_to_rgb((best_start + best_gap / 2) % 360)
+}
+
+/// Returns the hue of the color in degrees, or `None` for shades of gray
+fn rgb_to_theta(r: u8, g: u8, b: u8) -> Option<u32> {
+    let (r, g, b) = (f64::from(r), f64::from(g), f64::from(b));
+    let max = r.max(g).max(b);
+    let delta = max - r.min(g).min(b);
+    if delta == 0.0 {
+        return None;
+    }
+
+    let h_prime = if max == r {
+        ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / delta + 2.0
+    } else {
+        (r - g) / delta + 4.0
+    };
+    Some((h_prime * 60.0).round() as u32 % 360)
+}
+
 fn theta_to_rgb(theta: u32) -> Color {
     let c = 1.0;
     let h_prime = f64::from(theta) / 60.0;
@@ -140,6 +210,7 @@ fn theta_to_rgb(theta: u32) -> Color {
 #[cfg(test)]
 mod tests {
 
+    use super::distinct_color;
     use super::theta_to_rgb;
     use super::Color;
 
@@ -174,4 +245,26 @@ mod tests {
         assert_eq!(Color::RGB(157, 0, 255), fifth_rand);
         assert_eq!(Color::RGB(255, 0, 25), sixth_rand);
     }
+
+    #[test]
+    fn test_distinct_color() {
+        assert_eq!(
+            distinct_color(&[Color::RED, Color::GREEN, Color::Default]),
+            theta_to_rgb(240)
+        );
+        assert_eq!(
+            distinct_color(&[Color::RED, Color::YELLOW, Color::BLUE]),
+            theta_to_rgb(150)
+        );
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!("Red".parse::<Color>(), Ok(Color::RED));
+        assert_eq!("random".parse::<Color>(), Ok(Color::Random));
+        assert_eq!("#ff8000".parse::<Color>(), Ok(Color::RGB(255, 128, 0)));
+        assert!("#ff80".parse::<Color>().is_err());
+        assert!("#+ff800".parse::<Color>().is_err());
+        assert!("purple".parse::<Color>().is_err());
+    }
 }
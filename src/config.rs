@@ -0,0 +1,571 @@
+use super::command_from_argv;
+use super::command_from_string;
+use super::Color;
+use super::Command;
+use super::CommandError;
+use super::RestartOn;
+use super::RestartOptions;
+use super::RestartPolicy;
+use super::Runner;
+use super::WatchPattern;
+use serde::de;
+use serde::de::value::MapAccessDeserializer;
+use serde::Deserialize;
+use serde::Deserializer;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// The formats a config file can be written in (see [Runner::from_config_file]). Requires the `config` feature.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Yaml,
+}
+
+impl ConfigFormat {
+    /// Picks the format from the extension of `path`: `.toml` for TOML, `.yaml` or `.yml` for YAML.
+    /// Returns `None` for any other extension.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        match path.as_ref().extension()?.to_str()? {
+            "toml" => Some(ConfigFormat::Toml),
+            "yaml" | "yml" => Some(ConfigFormat::Yaml),
+            _ => None,
+        }
+    }
+}
+
+thread_local! {
+    /// The directory that working directories of commands are relative to while a config is being deserialized.
+    /// Serde has no way to pass context down to nested types, so it is handed over on the side.
+    static BASE_DIR: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, bound = "C: Command")]
+struct ConfigFile<C> {
+    restart: Option<RestartConfig>,
+    quiet: Option<bool>,
+    file_handle_flags: Option<bool>,
+    process_groups: Option<bool>,
+    kill_on_parent_death: Option<bool>,
+    child_subreaper: Option<bool>,
+    max_parallel: Option<usize>,
+    watch_debounce_ms: Option<u64>,
+    watch_after_exit: Option<bool>,
+    start_message_template: Option<String>,
+    done_message_template: Option<String>,
+    payload_message_template: Option<String>,
+    error_message_template: Option<String>,
+    commands: Vec<CommandEntry<C>>,
+}
+
+impl<C: Command> ConfigFile<C> {
+    fn into_runner(self) -> Runner<C> {
+        let mut runner = Runner::new();
+        if let Some(RestartConfig(restart)) = self.restart {
+            runner.restart(restart);
+        }
+        if let Some(quiet) = self.quiet {
+            runner.quiet(quiet);
+        }
+        if let Some(file_handle_flags) = self.file_handle_flags {
+            runner.should_show_file_handle(file_handle_flags);
+        }
+        if let Some(process_groups) = self.process_groups {
+            runner.process_groups(process_groups);
+        }
+        if let Some(kill_on_parent_death) = self.kill_on_parent_death {
+            runner.kill_on_parent_death(kill_on_parent_death);
+        }
+        if let Some(child_subreaper) = self.child_subreaper {
+            runner.child_subreaper(child_subreaper);
+        }
+        if let Some(max_parallel) = self.max_parallel {
+            runner.max_parallel(max_parallel);
+        }
+        if let Some(debounce) = self.watch_debounce_ms {
+            runner.watch_debounce(Duration::from_millis(debounce));
+        }
+        if let Some(watch_after_exit) = self.watch_after_exit {
+            runner.watch_after_exit(watch_after_exit);
+        }
+        if let Some(template) = self.start_message_template {
+            runner.start_message_template(template);
+        }
+        if let Some(template) = self.done_message_template {
+            runner.done_message_template(template);
+        }
+        if let Some(template) = self.payload_message_template {
+            runner.payload_message_template(template);
+        }
+        if let Some(template) = self.error_message_template {
+            runner.error_message_template(template);
+        }
+        runner.commands = self.commands.into_iter().map(|entry| entry.0).collect();
+        runner
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CommandConfig {
+    name: String,
+    command: Option<String>,
+    argv: Option<Vec<String>>,
+    cwd: Option<PathBuf>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    color: Option<ColorConfig>,
+    restart: Option<RestartConfig>,
+    #[serde(default)]
+    watch: Vec<String>,
+}
+
+impl CommandConfig {
+    fn build<C: Command>(self, base_dir: Option<&Path>) -> Result<C, CommandError> {
+        let dir = match (base_dir, self.cwd) {
+            (Some(base_dir), Some(cwd)) => Some(base_dir.join(cwd)),
+            (Some(base_dir), None) => Some(base_dir.to_path_buf()),
+            (None, cwd) => cwd,
+        };
+        // the executable is looked up in the PATH the command runs with
+        let path_var = self.env.get("PATH").map(OsStr::new);
+        let mut cmd: C = match (self.command, self.argv) {
+            (Some(command), None) => command_from_string(self.name, command, dir, path_var)?,
+            (None, Some(argv)) => match argv.split_first() {
+                Some((command, args)) => {
+                    command_from_argv(self.name, command.clone(), args.to_vec(), dir, path_var)?
+                }
+                None => return Err(CommandError::EmptyCommand),
+            },
+            _ => {
+                return Err(CommandError::ParseError(
+                    "exactly one of `command` and `argv` must be set".to_string(),
+                ))
+            }
+        };
+
+        for (key, val) in self.env {
+            cmd.env(key, val);
+        }
+        if let Some(ColorConfig(color)) = self.color {
+            cmd.set_color(color);
+        }
+        if let Some(RestartConfig(restart)) = self.restart {
+            cmd.restart(restart);
+        }
+        for pattern in self.watch {
+            cmd.watch(WatchPattern::new(pattern)?);
+        }
+        Ok(cmd)
+    }
+}
+
+/// A command of a config file, built as soon as its entry has been read
+struct CommandEntry<C>(C);
+
+impl<'de, C: Command> Deserialize<'de> for CommandEntry<C> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(CommandVisitor(PhantomData))
+    }
+}
+
+struct CommandVisitor<C>(PhantomData<C>);
+
+impl<'de, C: Command> de::Visitor<'de> for CommandVisitor<C> {
+    type Value = CommandEntry<C>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a command")
+    }
+
+    // The command is built while the deserializer is still inside the entry, so that errors point at the entry
+    fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        let config = CommandConfig::deserialize(MapAccessDeserializer::new(map))?;
+        let name = config.name.clone();
+        let base_dir = BASE_DIR.with(|dir| dir.borrow().clone());
+        config
+            .build(base_dir.as_deref())
+            .map(CommandEntry)
+            .map_err(|e| de::Error::custom(format!("command '{}': {}", name, e)))
+    }
+}
+
+/// Either a color name (`red`, `green`, `yellow`, `blue`, `magenta`, `cyan`, `white`, `black`, `random` or `default`) or `#rrggbb`
+struct ColorConfig(Color);
+
+impl<'de> Deserialize<'de> for ColorConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map(ColorConfig)
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Either `continue`, `restart`, `kill` or `kill_on_exit`, or a table describing a [RestartPolicy]
+struct RestartConfig(RestartOptions);
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RestartOnConfig {
+    Failure,
+    Always,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RestartPolicyConfig {
+    restart_on: Option<RestartOnConfig>,
+    max_attempts: Option<u32>,
+    initial_delay_ms: Option<u64>,
+    max_delay_ms: Option<u64>,
+    multiplier: Option<f64>,
+    jitter: Option<f64>,
+    /// 0 never resets the attempts
+    reset_after_ms: Option<u64>,
+}
+
+impl<'de> Deserialize<'de> for RestartConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(RestartVisitor)
+    }
+}
+
+struct RestartVisitor;
+
+impl<'de> de::Visitor<'de> for RestartVisitor {
+    type Value = RestartConfig;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("`continue`, `restart`, `kill`, `kill_on_exit` or a restart policy")
+    }
+
+    fn visit_str<E: de::Error>(self, mode: &str) -> Result<Self::Value, E> {
+        match mode {
+            "continue" => Ok(RestartConfig(RestartOptions::Continue)),
+            "restart" => Ok(RestartConfig(RestartOptions::Restart)),
+            "kill" => Ok(RestartConfig(RestartOptions::Kill)),
+            "kill_on_exit" => Ok(RestartConfig(RestartOptions::KillOnExit)),
+            _ => Err(E::invalid_value(de::Unexpected::Str(mode), &self)),
+        }
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        let config = RestartPolicyConfig::deserialize(MapAccessDeserializer::new(map))?;
+        for (field, value) in [("multiplier", config.multiplier), ("jitter", config.jitter)] {
+            if value.is_some_and(|value| !value.is_finite()) {
+                return Err(de::Error::custom(format!(
+                    "`{}` must be a finite number",
+                    field
+                )));
+            }
+        }
+        let default = RestartPolicy::default();
+        let policy = RestartPolicy {
+            restart_on: match config.restart_on {
+                Some(RestartOnConfig::Always) => RestartOn::Always,
+                Some(RestartOnConfig::Failure) | None => RestartOn::Failure,
+            },
+            max_attempts: config.max_attempts,
+            initial_delay: config
+                .initial_delay_ms
+                .map(Duration::from_millis)
+                .unwrap_or(default.initial_delay),
+            max_delay: config
+                .max_delay_ms
+                .map(Duration::from_millis)
+                .unwrap_or(default.max_delay),
+            multiplier: config.multiplier.unwrap_or(default.multiplier),
+            jitter: config.jitter.unwrap_or(default.jitter),
+            reset_after: match config.reset_after_ms {
+                Some(0) => None,
+                Some(ms) => Some(Duration::from_millis(ms)),
+                None => default.reset_after,
+            },
+        };
+        Ok(RestartConfig(RestartOptions::RestartWith(policy)))
+    }
+}
+
+impl<C: Command> Runner<C> {
+    /// Loads a runner from a TOML (`.toml`) or YAML (`.yaml` or `.yml`) file. Requires the `config` feature.
+    ///
+    /// The top level of the file may set the options of the runner: `restart`, `quiet`, `file_handle_flags` (see [Runner::should_show_file_handle]),
+    /// `process_groups`, `kill_on_parent_death`, `child_subreaper`, `max_parallel`, `watch_debounce_ms`, `watch_after_exit` and the four `*_message_template`s.
+    /// Options that are left out keep their defaults. `commands` lists the commands, each with a `name` and either
+    /// a `command` string (see [Command::from_string]) or an `argv` list (see [Command::from_argv]), and optionally
+    /// a `cwd`, an `env` table, a `color` (a name like `red`, `random` or `default`, or `#rrggbb`; ignored by APIs without colors), a `restart`
+    /// and a `watch` list of glob patterns (see [Command::watch]).
+    ///
+    /// A `restart` is either `continue`, `restart`, `kill` or `kill_on_exit` (see [RestartOptions]) or a table with any of the fields `restart_on`
+    /// (`failure` or `always`), `max_attempts`, `initial_delay_ms`, `max_delay_ms`, `multiplier`, `jitter` and `reset_after_ms`
+    /// (0 never resets), which restarts the command with that [RestartPolicy].
+    ///
+    /// Commands run in the directory that contains the file and a relative `cwd` is relative to that directory.
+    /// Returns [CommandError::ReadError] if the file can't be read or [CommandError::InvalidConfig] with the line of the
+    /// offending entry if it can't be parsed or one of the commands can't be constructed.
+    /// ## Example
+    /// ```toml
+    /// restart = "kill"
+    /// quiet = true
+    ///
+    /// [[commands]]
+    /// name = "server"
+    /// command = "python3 -m http.server 8000"
+    /// cwd = "public"
+    /// color = "green"
+    /// restart = { restart_on = "always", max_attempts = 5 }
+    ///
+    /// [[commands]]
+    /// name = "watcher"
+    /// argv = ["npm", "run", "watch"]
+    /// env = { NODE_ENV = "development" }
+    /// ```
+    pub fn from_config_file<P: AsRef<Path>>(path: P) -> Result<Self, CommandError> {
+        let path = path.as_ref();
+        let source = path.display().to_string();
+        let format = ConfigFormat::from_path(path).ok_or_else(|| {
+            CommandError::InvalidConfig(
+                source.clone(),
+                None,
+                "unknown format, expected a .toml, .yaml or .yml file".to_string(),
+            )
+        })?;
+        let contents = fs::read_to_string(path)
+            .map_err(|e| CommandError::ReadError(source.clone(), e.to_string()))?;
+        let base_dir = path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .map(|dir| dir.to_path_buf());
+        load(&source, &contents, format, base_dir)
+    }
+
+    /// Loads a runner from the contents of a config file (see [Runner::from_config_file]). Requires the `config` feature.
+    /// Commands run in the current working directory of this process.
+    /// ## Example
+    /// ```
+    /// use currant::{ChannelCommand, ConfigFormat, Runner};
+    ///
+    /// let config = "
+    /// quiet: true
+    /// commands:
+    ///   - name: hello
+    ///     argv: [echo, hello]
+    /// ";
+    /// let mut runner: Runner<ChannelCommand> = Runner::from_config_str(config, ConfigFormat::Yaml).unwrap();
+    /// let results = runner.execute().join().unwrap();
+    /// assert_eq!(results[0].name, "hello");
+    /// ```
+    pub fn from_config_str<S: AsRef<str>>(
+        contents: S,
+        format: ConfigFormat,
+    ) -> Result<Self, CommandError> {
+        load("<string>", contents.as_ref(), format, None)
+    }
+}
+
+fn load<C: Command>(
+    source: &str,
+    contents: &str,
+    format: ConfigFormat,
+    base_dir: Option<PathBuf>,
+) -> Result<Runner<C>, CommandError> {
+    BASE_DIR.with(|dir| *dir.borrow_mut() = base_dir);
+    let config = match format {
+        ConfigFormat::Toml => toml::from_str::<ConfigFile<C>>(contents).map_err(|e| {
+            let line = e.span().map(|span| line_of(contents, span.start));
+            CommandError::InvalidConfig(source.to_string(), line, e.message().to_string())
+        }),
+        ConfigFormat::Yaml => serde_yaml::from_str::<ConfigFile<C>>(contents).map_err(|e| {
+            let line = e.location().map(|location| location.line());
+            let message = e.to_string();
+            // the location is reported on its own
+            let message = match message.rsplit_once(" at line ") {
+                Some((message, _)) if line.is_some() => message.to_string(),
+                _ => message,
+            };
+            CommandError::InvalidConfig(source.to_string(), line, message)
+        }),
+    };
+    BASE_DIR.with(|dir| *dir.borrow_mut() = None);
+    Ok(config?.into_runner())
+}
+
+/// The line (starting at 1) that the byte at `offset` is on
+fn line_of(contents: &str, offset: usize) -> usize {
+    contents.as_bytes()[..offset.min(contents.len())]
+        .iter()
+        .filter(|byte| **byte == b'\n')
+        .count()
+        + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ConfigFormat;
+    use crate::ChannelCommand;
+    use crate::Color;
+    use crate::Command;
+    use crate::CommandError;
+    use crate::ConsoleCommand;
+    use crate::OutputMessagePayload;
+    use crate::RestartOptions;
+    use crate::Runner;
+    use std::time::Duration;
+
+    #[test]
+    fn loads_toml_and_yaml() {
+        let toml = r##"
+quiet = true
+file_handle_flags = true
+restart = "kill"
+max_parallel = 1
+done_message_template = "{{name}} done"
+
+[[commands]]
+name = "greet"
+command = "sh -c 'echo $GREETING'"
+env = { GREETING = "hello" }
+color = "#ff8000"
+
+[[commands]]
+name = "list"
+argv = ["ls", "-a"]
+cwd = "/"
+color = "cyan"
+restart = { restart_on = "always", max_attempts = 2, reset_after_ms = 0 }
+"##;
+        let yaml = r##"
+quiet: true
+file_handle_flags: true
+restart: kill
+max_parallel: 1
+done_message_template: "{{name}} done"
+commands:
+  - name: greet
+    command: sh -c 'echo $GREETING'
+    env:
+      GREETING: hello
+    color: "#ff8000"
+  - name: list
+    argv: [ls, -a]
+    cwd: /
+    color: cyan
+    restart:
+      restart_on: always
+      max_attempts: 2
+      reset_after_ms: 0
+"##;
+
+        for (contents, format) in [(toml, ConfigFormat::Toml), (yaml, ConfigFormat::Yaml)] {
+            let runner: Runner<ConsoleCommand> = Runner::from_config_str(contents, format).unwrap();
+            assert!(runner.quiet);
+            assert!(runner.file_handle_flags);
+            assert!(matches!(runner.restart, RestartOptions::Kill));
+            assert_eq!(runner.max_parallel, Some(1));
+            assert_eq!(runner.done_message_template, "{{name}} done");
+
+            let greet = &runner.commands[0];
+            assert_eq!(greet.get_color(), Some(Color::rgb(255, 128, 0)));
+            assert_eq!(greet.get_command().env["GREETING"], "hello");
+
+            let list = &runner.commands[1];
+            assert_eq!(list.get_color(), Some(Color::CYAN));
+            assert_eq!(list.get_command().args, vec!["-a"]);
+            match &list.get_command().restart {
+                Some(RestartOptions::RestartWith(policy)) => {
+                    assert_eq!(policy.max_attempts, Some(2));
+                    assert_eq!(policy.reset_after, None);
+                }
+                _ => panic!("expected a restart policy"),
+            }
+        }
+
+        let mut runner: Runner<ChannelCommand> =
+            Runner::from_config_str(toml, ConfigFormat::Toml).unwrap();
+        let handle = runner.execute();
+        let mut greeting = Vec::new();
+        for msg in &handle {
+            if let OutputMessagePayload::Stdout(_, bytes) = msg.message {
+                if msg.name == "greet" {
+                    greeting = bytes;
+                }
+            }
+        }
+        handle.join().unwrap();
+        assert_eq!(greeting, b"hello");
+
+        let runner: Runner<ChannelCommand> = Runner::from_config_str(
+            "watch_debounce_ms: 500\nwatch_after_exit: true\ncommands:\n  - name: dev\n    command: 'true'\n    watch: ['src/**/*.rs', Cargo.toml]\n",
+            ConfigFormat::Yaml,
+        )
+        .unwrap();
+        assert_eq!(runner.watch_debounce, Duration::from_millis(500));
+        assert!(runner.watch_after_exit);
+        assert_eq!(runner.commands[0].get_command().watch.len(), 2);
+    }
+
+    #[test]
+    fn reports_lines() {
+        let cases = [
+            (
+                "[[commands]]\nname = \"a\"\ncommand = \"true\"\n\n[[commands]]\nname = \"b\"\ncolor = \"purple\"\ncommand = \"true\"\n",
+                ConfigFormat::Toml,
+                7,
+            ),
+            (
+                "[[commands]]\nname = \"a\"\ncommand = \"true\"\n\n[[commands]]\nname = \"b\"\ncommand = \"this_command_does_not_exist\"\n",
+                ConfigFormat::Toml,
+                5,
+            ),
+            (
+                "[[commands]]\nname = \"a\"\ncommand = \"true\"\nrestart = { jitter = nan }\n",
+                ConfigFormat::Toml,
+                4,
+            ),
+            (
+                "quiet = true\ncommands = []\nverbose = true\n",
+                ConfigFormat::Toml,
+                3,
+            ),
+            (
+                "commands:\n  - name: a\n    command: 'true'\n  - name: b\n    argv: []\n",
+                ConfigFormat::Yaml,
+                4,
+            ),
+            (
+                "commands:\n  - name: a\n    command: 'true'\n    restart: sometimes\n",
+                ConfigFormat::Yaml,
+                4,
+            ),
+            (
+                "commands:\n  - name: a\n    command: 'true'\n  - name: b\n    command: 'true'\n    watch: ['src/[ab']\n",
+                ConfigFormat::Yaml,
+                4,
+            ),
+        ];
+
+        for (contents, format, expected_line) in cases {
+            match Runner::<ChannelCommand>::from_config_str(contents, format) {
+                Err(CommandError::InvalidConfig(source, line, _)) => {
+                    assert_eq!(source, "<string>");
+                    assert_eq!(line, Some(expected_line), "{}", contents);
+                }
+                _ => panic!("expected an invalid config: {}", contents),
+            }
+        }
+
+        assert!(matches!(
+            Runner::<ChannelCommand>::from_config_file("stack.json"),
+            Err(CommandError::InvalidConfig(_, None, _))
+        ));
+    }
+}
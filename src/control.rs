@@ -1,41 +1,78 @@
 pub use nix::sys::signal::Signal;
+use std::process::ChildStdin;
 use std::sync::Arc;
 use std::sync::Mutex;
 
+use crate::child::SharedChild;
 use crate::kill_barrier::KillBarrier;
 
+/// A command name paired with its currently running process (if any)
+pub struct CommandEntry {
+    pub name: String,
+    pub child: Mutex<Option<Arc<SharedChild>>>,
+    /// Standard in of the current run, for commands with [StdinMode::Interactive](crate::StdinMode::Interactive)
+    pub stdin: Mutex<Option<Arc<ChildStdin>>>,
+    pub interactive: bool,
+    /// Thrown to stop just this command. Thrown as well when all commands are killed
+    pub kill_trigger: KillBarrier,
+}
+
+pub(crate) type PidEntry = Arc<CommandEntry>;
+
+/// The entries of all commands that are part of a handle. Commands are added and removed while they run
+pub(crate) type PidTable = Arc<Mutex<Vec<PidEntry>>>;
+
+impl CommandEntry {
+    pub fn new(name: &str, kill_trigger: KillBarrier, interactive: bool) -> PidEntry {
+        Arc::new(CommandEntry {
+            name: name.to_string(),
+            child: Mutex::new(None),
+            stdin: Mutex::new(None),
+            interactive,
+            kill_trigger,
+        })
+    }
+}
+
 /// Provides a way to send signals to the underlying processes.
+/// Commands added to or removed from the handle later on (see [CommandHandle::add_command](crate::CommandHandle::add_command))
+/// are added to or removed from the [HandleControl] as well.
 pub struct HandleControl {
-    pids: Vec<Arc<(String, Mutex<Option<u32>>)>>,
+    pids: PidTable,
     kill_barrier: KillBarrier,
 }
 
 impl HandleControl {
     /// Construct a new [HandleControl].
     /// This shouldn't really be called. Use [CommandHandle::get_signaler](crate::CommandHandle::get_signaler) and [ControlledCommandHandle::get_signaler](crate::ControlledCommandHandle::get_signaler) instead
-    pub fn new(pids: Vec<Arc<(String, Mutex<Option<u32>>)>>, barrier: KillBarrier) -> Self {
+    pub fn new(pids: PidTable, barrier: KillBarrier) -> Self {
         Self {
             pids,
             kill_barrier: barrier,
         }
     }
 
-    /// Kills all running processes. This uses the kill barrier functionality and will work on all OS-es. This won't send a SIGTERM to all processes
+    /// Kills all running processes. This uses the kill barrier functionality and will work on all OS-es.
+    /// Each process is stopped according to its [StopSequence](crate::StopSequence) (by default `SIGTERM` followed by `SIGKILL`)
     pub fn kill_all(&self) -> Result<(), String> {
         self.kill_barrier.initiate_kill()
     }
 
     /// UNIX-ONLY: Send a unix signal to a specific child process by name.
     /// See [Signal] for variants.
+    /// When commands run in their own process group (see [Runner::process_groups](crate::Runner::process_groups)), the whole group receives the signal.
     /// On windows machines this will most likely just kill the child process.
     /// Returns `()` on success or an error message if the signal couldn't be sent
     pub fn signal_one(&self, cmd_name: &str, signal: Signal) -> Result<(), String> {
-        for pid_arc in self.pids.iter() {
-            let (name, lock) = &**pid_arc;
-            if name == cmd_name {
-                if let Ok(unlocked_pid) = lock.lock() {
-                    if let Some(pid) = &*unlocked_pid {
-                        return send_signal(*pid, signal);
+        let pids = self
+            .pids
+            .lock()
+            .map_err(|_| "Unable to acquire poisoned lock for pidlist".to_string())?;
+        for pid_arc in pids.iter() {
+            if pid_arc.name == cmd_name {
+                if let Ok(unlocked_pid) = pid_arc.child.lock() {
+                    if let Some(child) = &*unlocked_pid {
+                        return child.signal(signal);
                     } else {
                         return Err(format!("Unable to look up pid for cmd: {}", cmd_name));
                     }
@@ -53,20 +90,20 @@ impl HandleControl {
 
     /// UNIX-ONLY: Send a unix signal to all child processes.
     /// See [Signal] for variants.
+    /// When commands run in their own process group (see [Runner::process_groups](crate::Runner::process_groups)), the whole groups receive the signal.
     /// On windows machines this will most likely just kill all child processes.
     /// If an error occurs sending a message to a specific process, currant will silently move on to the next child process
     pub fn signal_all(&self, signal: Signal) {
-        for pid_arc in self.pids.iter() {
-            if let Ok(unlocked_pid) = pid_arc.1.lock() {
-                if let Some(pid) = &*unlocked_pid {
-                    let _ = send_signal(*pid, signal);
+        let pids = match self.pids.lock() {
+            Ok(pids) => pids,
+            Err(_) => return,
+        };
+        for pid_arc in pids.iter() {
+            if let Ok(unlocked_pid) = pid_arc.child.lock() {
+                if let Some(child) = &*unlocked_pid {
+                    let _ = child.signal(signal);
                 }
             }
         }
     }
 }
-
-fn send_signal(pid: u32, signal: Signal) -> Result<(), String> {
-    nix::sys::signal::kill(nix::unistd::Pid::from_raw(pid as i32), signal)
-        .map_err(|e| e.to_string())
-}
@@ -0,0 +1,353 @@
+use super::kill_barrier::KillBarrier;
+use super::CommandError;
+use super::InnerCommand;
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// How often a command waiting on its dependencies re-checks whether it has been killed
+const KILL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The condition a dependency has to meet before the dependent command is started.
+/// See [Command::depends_on](crate::Command::depends_on).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DependencyCondition {
+    /// The dependency's process has been spawned.
+    Started,
+    /// The dependency has exited with a successful status and won't be restarted.
+    ExitedSuccessfully,
+    /// The dependency is ready to accept work.
+    /// A command is ready once its readiness probe passes (see [Command::ready_when](crate::Command::ready_when)).
+    /// Commands without a probe are ready as soon as their process has been spawned.
+    Ready,
+}
+
+#[derive(Default)]
+struct CommandState {
+    started: bool,
+    ready: bool,
+    exited: Option<bool>,
+    /// Exited, but waits for changes to its watched files (see [Runner::watch_after_exit](crate::Runner::watch_after_exit))
+    waiting: bool,
+}
+
+impl CommandState {
+    /// Returns `Some(true)` when `condition` is met, `Some(false)` when it can never be met and `None` if it isn't known yet
+    fn satisfies(&self, condition: DependencyCondition) -> Option<bool> {
+        let met = match condition {
+            DependencyCondition::Started => self.started,
+            DependencyCondition::ExitedSuccessfully => self.exited == Some(true),
+            DependencyCondition::Ready => self.ready,
+        };
+
+        if met {
+            Some(true)
+        } else if self.exited.is_some() {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
+/// Tracks the lifecycle of every command so that dependent commands know when they are allowed to start
+#[derive(Clone)]
+pub struct DependencyTracker {
+    state: Arc<(Mutex<HashMap<String, CommandState>>, Condvar)>,
+}
+
+impl DependencyTracker {
+    pub fn new() -> Self {
+        DependencyTracker {
+            state: Arc::new((Mutex::new(HashMap::new()), Condvar::new())),
+        }
+    }
+
+    /// Starts tracking a command from scratch, forgetting about any earlier command with the same name.
+    /// Returns false (and leaves the state alone) if a command with the same name is still around.
+    pub fn register(&self, name: &str) -> bool {
+        let (lock, cvar) = &*self.state;
+        let mut states = match lock.lock() {
+            Ok(states) => states,
+            Err(_) => return false,
+        };
+        if let Some(CommandState { exited: None, .. } | CommandState { waiting: true, .. }) =
+            states.get(name)
+        {
+            return false;
+        }
+        states.insert(name.to_string(), CommandState::default());
+        cvar.notify_all();
+        true
+    }
+
+    pub fn mark_started(&self, name: &str) {
+        self.update(name, |state| {
+            state.started = true;
+            if state.waiting {
+                state.exited = None;
+                state.waiting = false;
+            }
+        });
+    }
+
+    pub fn mark_ready(&self, name: &str) {
+        self.update(name, |state| state.ready = true);
+    }
+
+    /// Marks the command as finished for good (no more restarts)
+    pub fn mark_exited(&self, name: &str, success: bool) {
+        self.update(name, |state| {
+            state.exited = Some(success);
+            state.waiting = false;
+        });
+    }
+
+    /// Marks the command as exited while it waits for changes to its watched files, after which it is started again
+    pub fn mark_waiting(&self, name: &str, success: bool) {
+        self.update(name, |state| {
+            state.exited = Some(success);
+            state.waiting = true;
+        });
+    }
+
+    fn update<F: FnOnce(&mut CommandState)>(&self, name: &str, func: F) {
+        let (lock, cvar) = &*self.state;
+        if let Ok(mut states) = lock.lock() {
+            func(states.entry(name.to_string()).or_default());
+            cvar.notify_all();
+        }
+    }
+
+    /// Blocks until every dependency meets its condition.
+    /// Returns `Ok(true)` once they are all met, `Ok(false)` if the kill switch was thrown while waiting,
+    /// and an error message if one of the dependencies can never meet its condition.
+    pub fn wait_for(
+        &self,
+        dependencies: &[(String, DependencyCondition)],
+        kill_trigger: &KillBarrier,
+    ) -> Result<bool, String> {
+        let (lock, cvar) = &*self.state;
+        let mut states = lock.lock().map_err(|e| e.to_string())?;
+        loop {
+            let mut all_met = true;
+            for (dep_name, condition) in dependencies {
+                let satisfied = states
+                    .get(dep_name)
+                    .and_then(|state| state.satisfies(*condition));
+                match satisfied {
+                    Some(true) => {}
+                    Some(false) => {
+                        return Err(format!(
+                            "dependency '{}' exited without meeting condition {:?}",
+                            dep_name, condition
+                        ));
+                    }
+                    None => all_met = false,
+                }
+            }
+
+            if all_met {
+                return Ok(true);
+            }
+
+            if kill_trigger.is_killed() {
+                return Ok(false);
+            }
+
+            states = cvar
+                .wait_timeout(states, KILL_POLL_INTERVAL)
+                .map_err(|e| e.to_string())?
+                .0;
+        }
+    }
+}
+
+/// Checks that every dependency refers to a known command and that there are no cycles.
+/// Returns the indices of `commands` in an order where every command comes after its dependencies.
+pub fn validate(commands: &[InnerCommand]) -> Result<Vec<usize>, CommandError> {
+    let mut indices: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (idx, cmd) in commands.iter().enumerate() {
+        indices.entry(cmd.name.as_str()).or_default().push(idx);
+    }
+
+    for cmd in commands {
+        for (dep_name, _) in &cmd.depends_on {
+            if !indices.contains_key(dep_name.as_str()) {
+                return Err(CommandError::UnknownDependency(
+                    cmd.name.clone(),
+                    dep_name.clone(),
+                ));
+            }
+        }
+    }
+
+    let mut marks = vec![Mark::Unvisited; commands.len()];
+    let mut order = Vec::new();
+    let mut path = Vec::new();
+    for idx in 0..commands.len() {
+        visit(idx, commands, &indices, &mut marks, &mut path, &mut order)?;
+    }
+
+    Ok(order)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mark {
+    Unvisited,
+    InProgress,
+    Done,
+}
+
+fn visit(
+    idx: usize,
+    commands: &[InnerCommand],
+    indices: &HashMap<&str, Vec<usize>>,
+    marks: &mut Vec<Mark>,
+    path: &mut Vec<usize>,
+    order: &mut Vec<usize>,
+) -> Result<(), CommandError> {
+    match marks[idx] {
+        Mark::Done => return Ok(()),
+        Mark::InProgress => {
+            let cycle_start = path.iter().position(|i| *i == idx).unwrap_or(0);
+            let mut cycle: Vec<String> = path[cycle_start..]
+                .iter()
+                .map(|i| commands[*i].name.clone())
+                .collect();
+            cycle.push(commands[idx].name.clone());
+            return Err(CommandError::DependencyCycle(cycle));
+        }
+        Mark::Unvisited => {}
+    }
+
+    marks[idx] = Mark::InProgress;
+    path.push(idx);
+    for (dep_name, _) in &commands[idx].depends_on {
+        for dep_idx in &indices[dep_name.as_str()] {
+            visit(*dep_idx, commands, indices, marks, path, order)?;
+        }
+    }
+    path.pop();
+    marks[idx] = Mark::Done;
+    order.push(idx);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::validate;
+    use super::DependencyCondition;
+    use crate::ChannelCommand;
+    use crate::Command;
+    use crate::CommandError;
+    use crate::InnerCommand;
+    use crate::OutputMessagePayload;
+    use crate::Runner;
+    use crate::CURRENT_WORKING_DIRECTORY;
+
+    fn cmd(name: &str, deps: &[&str]) -> InnerCommand {
+        let mut cmd = ChannelCommand::from_string(name, "true", CURRENT_WORKING_DIRECTORY).unwrap();
+        for dep in deps {
+            cmd.depends_on(*dep, DependencyCondition::Started);
+        }
+        cmd.get_command().clone()
+    }
+
+    #[test]
+    fn orders_dependencies_first() {
+        let commands = vec![
+            cmd("api", &["migrate"]),
+            cmd("migrate", &["db"]),
+            cmd("db", &[]),
+        ];
+        assert_eq!(validate(&commands).unwrap(), vec![2, 1, 0]);
+    }
+
+    #[test]
+    fn unknown_dependency() {
+        let commands = vec![cmd("api", &["bogus"])];
+        match validate(&commands) {
+            Err(CommandError::UnknownDependency(name, dep)) => {
+                assert_eq!(name, "api");
+                assert_eq!(dep, "bogus");
+            }
+            _ => panic!("unknown dependency wasn't reported"),
+        }
+    }
+
+    #[test]
+    fn dependency_cycle() {
+        let commands = vec![cmd("a", &["b"]), cmd("b", &["c"]), cmd("c", &["a"])];
+        match validate(&commands) {
+            Err(CommandError::DependencyCycle(cycle)) => {
+                assert_eq!(cycle, vec!["a", "b", "c", "a"]);
+            }
+            _ => panic!("cycle wasn't reported"),
+        }
+    }
+
+    #[test]
+    fn waits_for_dependency_to_exit() {
+        let handle = Runner::new()
+            .command(
+                ChannelCommand::from_string("server", "true", CURRENT_WORKING_DIRECTORY)
+                    .unwrap()
+                    .depends_on("migrate", DependencyCondition::ExitedSuccessfully),
+            )
+            .command(
+                ChannelCommand::from_string("migrate", "sleep 0.2", CURRENT_WORKING_DIRECTORY)
+                    .unwrap(),
+            )
+            .execute();
+
+        let mut events = Vec::new();
+        for msg in &handle {
+            match msg.message {
+                OutputMessagePayload::Start => events.push(format!("{} start", msg.name)),
+                OutputMessagePayload::Done(_) => events.push(format!("{} done", msg.name)),
+                _ => {}
+            }
+        }
+        handle.join().unwrap();
+
+        assert_eq!(
+            events,
+            vec![
+                "migrate start",
+                "migrate done",
+                "server start",
+                "server done"
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_dependency_skips_dependent() {
+        let handle = Runner::new()
+            .command(
+                ChannelCommand::from_string("migrate", "false", CURRENT_WORKING_DIRECTORY).unwrap(),
+            )
+            .command(
+                ChannelCommand::from_string("server", "true", CURRENT_WORKING_DIRECTORY)
+                    .unwrap()
+                    .depends_on("migrate", DependencyCondition::ExitedSuccessfully),
+            )
+            .execute();
+
+        let mut server_errored = false;
+        for msg in &handle {
+            if msg.name == "server" {
+                match msg.message {
+                    OutputMessagePayload::Error(_) => server_errored = true,
+                    _ => panic!("server shouldn't have started"),
+                }
+            }
+        }
+        let results = handle.join().unwrap();
+
+        assert!(server_errored);
+        assert!(results[1].status.is_none());
+    }
+}
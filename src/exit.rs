@@ -0,0 +1,373 @@
+use super::ExitResult;
+use nix::sys::signal::Signal;
+use std::fmt;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use std::str::FromStr;
+
+/// The exit code of an outcome decided by a run that timed out but exited with code 0, like the one of coreutils' `timeout`
+const TIMED_OUT_CODE: i32 = 124;
+
+/// How a run of a command ended.
+/// Reported via [OutputMessagePayload::Done](crate::OutputMessagePayload::Done) and [ExitResult::exit](crate::ExitResult::exit).
+/// Signals are raw signal numbers so that real-time signals can be represented as well.
+/// [Signal] can be used to convert them (for example, `Signal::try_from(signal)`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProcessExit {
+    /// The process exited on its own with the given exit code
+    Exited(i32),
+    /// The process was terminated by the given signal. The flag tells whether it dumped core.
+    Signaled(i32, bool),
+    /// The process was stopped (but not terminated) by the given signal
+    Stopped(i32),
+}
+
+impl ProcessExit {
+    /// Returns true if the process exited on its own with exit code 0
+    pub fn success(&self) -> bool {
+        *self == ProcessExit::Exited(0)
+    }
+
+    /// The exit code, if the process exited on its own
+    pub fn code(&self) -> Option<i32> {
+        match self {
+            ProcessExit::Exited(code) => Some(*code),
+            _ => None,
+        }
+    }
+
+    /// The signal that terminated or stopped the process, if any
+    pub fn signal(&self) -> Option<i32> {
+        match self {
+            ProcessExit::Exited(_) => None,
+            ProcessExit::Signaled(signal, _) | ProcessExit::Stopped(signal) => Some(*signal),
+        }
+    }
+}
+
+impl From<ExitStatus> for ProcessExit {
+    fn from(status: ExitStatus) -> Self {
+        if let Some(code) = status.code() {
+            ProcessExit::Exited(code)
+        } else if let Some(signal) = status.signal() {
+            ProcessExit::Signaled(signal, status.core_dumped())
+        } else if let Some(signal) = status.stopped_signal() {
+            ProcessExit::Stopped(signal)
+        } else {
+            ProcessExit::Exited(status.into_raw())
+        }
+    }
+}
+
+/// Describes the exit in words, for example `exited with code 1` or `killed by SIGSEGV (core dumped)`
+impl fmt::Display for ProcessExit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProcessExit::Exited(code) => write!(f, "exited with code {}", code),
+            ProcessExit::Signaled(signal, false) => write!(f, "killed by {}", signal_name(*signal)),
+            ProcessExit::Signaled(signal, true) => {
+                write!(f, "killed by {} (core dumped)", signal_name(*signal))
+            }
+            ProcessExit::Stopped(signal) => write!(f, "stopped by {}", signal_name(*signal)),
+        }
+    }
+}
+
+/// How the exit results of all commands are reduced to a single [ExitOutcome]. Set via [Runner::exit_policy](crate::Runner::exit_policy)
+/// and applied by `join_outcome` (for example [CommandHandle::join_outcome](crate::CommandHandle::join_outcome)).
+///
+/// The command that decides the outcome can also end the other commands: see [Runner::kill_when_decided](crate::Runner::kill_when_decided).
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub enum ExitPolicy {
+    /// (DEFAULT) Succeeds if every command succeeded. Otherwise the first command to fail decides
+    #[default]
+    All,
+    /// The first command to be done for good decides
+    First,
+    /// The last command to be done for good decides
+    Last,
+    /// The command with the given name decides
+    Command(String),
+}
+
+/// The single outcome that an [ExitPolicy] reduces the exit results to
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExitOutcome {
+    /// True if the outcome is a success
+    pub success: bool,
+    /// An exit code for the outcome, suitable for the exit code of this process: 0 on success, otherwise the exit code of the
+    /// deciding command, 128 plus the signal if it was killed by a signal (like a shell), 124 if it timed out but exited with
+    /// code 0 (like coreutils' `timeout`), or 1 if it never ran. A failed outcome never has code 0.
+    pub code: i32,
+    /// The name of the command that decided the outcome. `None` if every command succeeded under [ExitPolicy::All]
+    /// or if no command decided the outcome.
+    pub name: Option<String>,
+}
+
+impl ExitPolicy {
+    /// Reduces the exit results of a handle to a single outcome.
+    /// Commands that were [killed](ExitResult::killed) don't decide the outcome under [ExitPolicy::All], [ExitPolicy::First]
+    /// and [ExitPolicy::Last], unless every command was killed. A run that [timed out](ExitResult::timed_out) is a failure.
+    /// Without any results, the outcome is a success, except for [ExitPolicy::Command], which fails with code 1
+    /// if its command isn't part of the results.
+    pub fn outcome(&self, results: &[ExitResult]) -> ExitOutcome {
+        let candidates: Vec<&ExitResult> = if results.iter().all(|result| result.killed) {
+            results.iter().collect()
+        } else {
+            results.iter().filter(|result| !result.killed).collect()
+        };
+        let decider = match self {
+            ExitPolicy::All => candidates
+                .into_iter()
+                .filter(|result| !is_success(result))
+                .min_by_key(|result| result.elapsed),
+            ExitPolicy::First => candidates.into_iter().min_by_key(|result| result.elapsed),
+            ExitPolicy::Last => candidates.into_iter().max_by_key(|result| result.elapsed),
+            ExitPolicy::Command(name) => match results.iter().find(|result| &result.name == name) {
+                Some(result) => Some(result),
+                None => {
+                    return ExitOutcome {
+                        success: false,
+                        code: 1,
+                        name: None,
+                    }
+                }
+            },
+        };
+
+        match decider {
+            Some(result) => {
+                let success = is_success(result);
+                let code = match result.exit() {
+                    Some(ProcessExit::Exited(code)) => code,
+                    Some(ProcessExit::Signaled(signal, _)) | Some(ProcessExit::Stopped(signal)) => {
+                        128 + signal
+                    }
+                    None => 1,
+                };
+                ExitOutcome {
+                    success,
+                    // only a run that timed out can fail with code 0
+                    code: if code == 0 && !success {
+                        TIMED_OUT_CODE
+                    } else {
+                        code
+                    },
+                    name: Some(result.name.clone()),
+                }
+            }
+            None => ExitOutcome {
+                success: true,
+                code: 0,
+                name: None,
+            },
+        }
+    }
+
+    /// Returns true if the command with the exit `result` settles the outcome as soon as it is done for good.
+    /// A command that was killed never does, so that the commands killed in turn don't decide anything either.
+    pub(crate) fn decides(&self, result: &ExitResult) -> bool {
+        if result.killed {
+            return false;
+        }
+        match self {
+            ExitPolicy::All => !is_success(result),
+            ExitPolicy::First => true,
+            // the outcome is only known once every command is done, so there is nothing left to kill
+            ExitPolicy::Last => false,
+            ExitPolicy::Command(name) => &result.name == name,
+        }
+    }
+}
+
+impl FromStr for ExitPolicy {
+    type Err = String;
+
+    /// Parses `all`, `first`, `last` or `command-<name>`
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        match policy {
+            "all" => Ok(ExitPolicy::All),
+            "first" => Ok(ExitPolicy::First),
+            "last" => Ok(ExitPolicy::Last),
+            _ => match policy.strip_prefix("command-") {
+                Some(name) if !name.is_empty() => Ok(ExitPolicy::Command(name.to_string())),
+                _ => Err(format!(
+                    "invalid exit policy '{}', expected all, first, last or command-<name>",
+                    policy
+                )),
+            },
+        }
+    }
+}
+
+fn is_success(result: &ExitResult) -> bool {
+    !result.timed_out && result.exit().map(|exit| exit.success()).unwrap_or(false)
+}
+
+/// Returns the name of the signal (like `SIGTERM`) or its number if it has no name
+pub(crate) fn signal_name(signal: i32) -> String {
+    match Signal::try_from(signal) {
+        Ok(signal) => signal.as_str().to_string(),
+        Err(_) => signal.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ExitOutcome;
+    use super::ExitPolicy;
+    use super::ProcessExit;
+    use crate::ChannelCommand;
+    use crate::Command;
+    use crate::OutputMessagePayload;
+    use crate::Runner;
+    use crate::CURRENT_WORKING_DIRECTORY;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+
+    #[test]
+    fn reports_signals() {
+        let handle = Runner::new()
+            .command(ChannelCommand::from_string("ok", "true", CURRENT_WORKING_DIRECTORY).unwrap())
+            .command(
+                ChannelCommand::from_argv(
+                    "killed",
+                    "sh",
+                    ["-c", "kill -KILL $$"],
+                    CURRENT_WORKING_DIRECTORY,
+                )
+                .unwrap(),
+            )
+            .execute();
+
+        let mut exits = Vec::new();
+        for msg in &handle {
+            if let OutputMessagePayload::Done(exit) = msg.message {
+                exits.push((msg.name, exit));
+            }
+        }
+        let results = handle.join().unwrap();
+        exits.sort_by(|a, b| a.0.cmp(&b.0));
+
+        assert_eq!(
+            exits,
+            vec![
+                ("killed".to_string(), ProcessExit::Signaled(9, false)),
+                ("ok".to_string(), ProcessExit::Exited(0)),
+            ]
+        );
+        assert_eq!(results[1].exit(), Some(ProcessExit::Signaled(9, false)));
+        assert_eq!(results[1].exit().unwrap().to_string(), "killed by SIGKILL");
+    }
+
+    fn stage(name: &str, script: &str) -> ChannelCommand {
+        ChannelCommand::from_argv(name, "sh", ["-c", script], CURRENT_WORKING_DIRECTORY).unwrap()
+    }
+
+    #[test]
+    fn reduces_by_policy() {
+        let results = Runner::new()
+            .command(stage("slow", "sleep 0.4"))
+            .command(stage("fails", "sleep 0.2; exit 3"))
+            .command(stage("killed", "kill -KILL $$"))
+            .execute()
+            .join()
+            .unwrap();
+
+        let outcome = |policy: ExitPolicy| policy.outcome(&results);
+        assert_eq!(
+            outcome(ExitPolicy::All),
+            ExitOutcome {
+                success: false,
+                code: 137,
+                name: Some("killed".to_string()),
+            }
+        );
+        assert_eq!(outcome(ExitPolicy::First).name.as_deref(), Some("killed"));
+        assert_eq!(
+            outcome(ExitPolicy::Last),
+            ExitOutcome {
+                success: true,
+                code: 0,
+                name: Some("slow".to_string()),
+            }
+        );
+        assert_eq!(outcome(ExitPolicy::Command("fails".to_string())).code, 3);
+        assert_eq!(outcome(ExitPolicy::Command("missing".to_string())).code, 1);
+        assert!(ExitPolicy::All.outcome(&results[..1]).success);
+        assert_eq!(
+            "command-fails".parse(),
+            Ok(ExitPolicy::Command("fails".to_string()))
+        );
+        assert!("command-".parse::<ExitPolicy>().is_err());
+    }
+
+    #[test]
+    fn kills_when_decided() {
+        let handle = Runner::new()
+            .command(stage("server", "exec sleep 10"))
+            .command(stage("tests", "exit 0"))
+            .exit_policy(ExitPolicy::Command("tests".to_string()))
+            .kill_when_decided(true)
+            .execute();
+
+        let outcome = handle.join_outcome().unwrap();
+        assert!(outcome.success);
+        assert_eq!(outcome.name.as_deref(), Some("tests"));
+    }
+
+    #[test]
+    fn killed_commands_dont_decide() {
+        let handle = Runner::new()
+            .command(stage("removed", "exec sleep 10"))
+            .command(stage("slow", "sleep 0.5; exit 4"))
+            .exit_policy(ExitPolicy::First)
+            .kill_when_decided(true)
+            .execute();
+        handle.wait_until_ready("removed").unwrap();
+        handle.remove_command("removed").unwrap();
+
+        let results = handle.join().unwrap();
+        assert!(results[0].killed);
+        assert!(!results[1].killed);
+        // removing a command neither decides the outcome nor kills the other commands
+        assert_eq!(
+            ExitPolicy::First.outcome(&results),
+            ExitOutcome {
+                success: false,
+                code: 4,
+                name: Some("slow".to_string()),
+            }
+        );
+
+        // the command that failed under RestartOptions::Kill wasn't killed itself, so it still decides
+        let results = Runner::new()
+            .command(stage("quick", "true"))
+            .command(stage("fails", "sleep 0.2; exit 3"))
+            .command(stage("server", "exec sleep 10"))
+            .restart(crate::RestartOptions::Kill)
+            .execute()
+            .join()
+            .unwrap();
+        assert_eq!(
+            results
+                .iter()
+                .map(|result| result.killed)
+                .collect::<Vec<_>>(),
+            vec![false, false, true]
+        );
+        assert_eq!(ExitPolicy::All.outcome(&results).code, 3);
+
+        // a run that timed out failed, even if it exited cleanly once it was stopped
+        let mut timed_out = crate::ExitResult::new("timed_out".to_string(), None, true, false);
+        timed_out.status = Some(ExitStatus::from_raw(0));
+        assert_eq!(
+            ExitPolicy::All.outcome(&[timed_out]),
+            ExitOutcome {
+                success: false,
+                code: 124,
+                name: Some("timed_out".to_string()),
+            }
+        );
+    }
+}
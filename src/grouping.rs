@@ -0,0 +1,289 @@
+use super::OutputMessage;
+use super::OutputMessagePayload;
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::mpsc;
+use std::time::Duration;
+use std::time::Instant;
+
+/// How the console and writer APIs group the output of concurrent commands. Set via [Runner::output_grouping](crate::Runner::output_grouping).
+/// Grouping only changes when messages are written, not how they look: every line is still formatted with the templates and colors of its command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum OutputGrouping {
+    /// (DEFAULT) Every message is written as soon as it arrives, so the lines of concurrent commands interleave
+    #[default]
+    Interleaved,
+    /// The messages of every run of a command are held back and written as one contiguous block once the run is over,
+    /// similar to `parallel --group`. Output of commands that never exit only shows up once they are killed.
+    PerCommand,
+    /// The messages of every command are held back and written as one contiguous block once `lines` lines of output
+    /// have been buffered, once the command has been silent for `idle`, or once the run is over, whichever comes first.
+    Chunked { lines: usize, idle: Duration },
+}
+
+/// Where a message falls within the block of its command
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Boundary {
+    /// A line of output
+    Line,
+    /// Any other message that belongs to the current run, including errors
+    Event,
+    /// The last message of a run ([OutputMessagePayload::Done])
+    End,
+}
+
+impl Boundary {
+    pub(crate) fn of(message: &OutputMessagePayload) -> Self {
+        match message {
+            OutputMessagePayload::Stdout(..) | OutputMessagePayload::Stderr(..) => Boundary::Line,
+            OutputMessagePayload::Done(_) => Boundary::End,
+            OutputMessagePayload::Start
+            | OutputMessagePayload::Error(_)
+            | OutputMessagePayload::Ready
+            | OutputMessagePayload::Queued
+            | OutputMessagePayload::Stopped(_)
+            | OutputMessagePayload::Restarting { .. }
+            | OutputMessagePayload::TimedOut => Boundary::Event,
+        }
+    }
+}
+
+struct Block {
+    bytes: Vec<u8>,
+    lines: usize,
+    last_line: Instant,
+}
+
+/// Holds back formatted messages according to an [OutputGrouping] and writes them block by block
+pub(crate) struct Grouper {
+    grouping: OutputGrouping,
+    blocks: HashMap<String, Block>,
+}
+
+impl Grouper {
+    pub(crate) fn new(grouping: OutputGrouping) -> Self {
+        Grouper {
+            grouping,
+            blocks: HashMap::new(),
+        }
+    }
+
+    /// Waits for the next message. Blocks that have been idle for too long are written in the meantime.
+    /// Once the channel closes, every remaining block is written and `None` is returned.
+    pub(crate) fn recv<W: Write>(
+        &mut self,
+        chan: &mpsc::Receiver<OutputMessage>,
+        writer: &mut W,
+    ) -> Option<OutputMessage> {
+        loop {
+            let message = match self.next_deadline() {
+                Some(deadline) => {
+                    match chan.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                        Ok(message) => Some(message),
+                        Err(mpsc::RecvTimeoutError::Timeout) => {
+                            self.flush_idle(writer);
+                            continue;
+                        }
+                        Err(mpsc::RecvTimeoutError::Disconnected) => None,
+                    }
+                }
+                None => chan.recv().ok(),
+            };
+
+            if message.is_none() {
+                let names: Vec<String> = self.blocks.keys().cloned().collect();
+                for name in names {
+                    self.flush(&name, writer);
+                }
+            }
+            return message;
+        }
+    }
+
+    /// Adds the formatted message `bytes` of the command `name` to its block and writes the block if it is complete
+    pub(crate) fn write<W: Write>(
+        &mut self,
+        writer: &mut W,
+        name: &str,
+        boundary: Boundary,
+        bytes: &[u8],
+    ) {
+        let max_lines = match self.grouping {
+            OutputGrouping::Interleaved => {
+                let _ = writer.write_all(bytes);
+                return;
+            }
+            OutputGrouping::PerCommand => None,
+            OutputGrouping::Chunked { lines, .. } => Some(lines),
+        };
+
+        let block = self
+            .blocks
+            .entry(name.to_string())
+            .or_insert_with(|| Block {
+                bytes: Vec::new(),
+                lines: 0,
+                last_line: Instant::now(),
+            });
+        block.bytes.extend_from_slice(bytes);
+        if boundary == Boundary::Line {
+            block.lines += 1;
+            block.last_line = Instant::now();
+        }
+
+        let full = match max_lines {
+            Some(max_lines) => block.lines >= max_lines,
+            None => false,
+        };
+        if full || boundary == Boundary::End {
+            self.flush(name, writer);
+        }
+    }
+
+    /// The moment the earliest idle block is due, if there is one
+    fn next_deadline(&self) -> Option<Instant> {
+        let idle = match self.grouping {
+            OutputGrouping::Chunked { idle, .. } => idle,
+            _ => return None,
+        };
+        self.blocks
+            .values()
+            .filter(|block| block.lines > 0)
+            .map(|block| block.last_line + idle)
+            .min()
+    }
+
+    fn flush_idle<W: Write>(&mut self, writer: &mut W) {
+        let idle = match self.grouping {
+            OutputGrouping::Chunked { idle, .. } => idle,
+            _ => return,
+        };
+        let now = Instant::now();
+        let due: Vec<String> = self
+            .blocks
+            .iter()
+            .filter(|(_, block)| block.lines > 0 && block.last_line + idle <= now)
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in due {
+            self.flush(&name, writer);
+        }
+    }
+
+    fn flush<W: Write>(&mut self, name: &str, writer: &mut W) {
+        if let Some(block) = self.blocks.remove(name) {
+            if !block.bytes.is_empty() {
+                let _ = writer.write_all(&block.bytes);
+                let _ = writer.flush();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OutputGrouping;
+    use crate::writer_api::SharedBuffer;
+    use crate::Command;
+    use crate::Runner;
+    use crate::WriterCommand;
+    use crate::CURRENT_WORKING_DIRECTORY;
+    use std::time::Duration;
+
+    fn run_grouped(grouping: OutputGrouping) -> Vec<String> {
+        let buffer = SharedBuffer::default();
+        let handle = Runner::new()
+            .command(
+                WriterCommand::from_argv(
+                    "a",
+                    "sh",
+                    ["-c", "echo 1; sleep 0.2; echo 2; sleep 0.2; echo 3"],
+                    CURRENT_WORKING_DIRECTORY,
+                )
+                .unwrap(),
+            )
+            .command(
+                WriterCommand::from_argv(
+                    "b",
+                    "sh",
+                    ["-c", "sleep 0.1; echo 1; sleep 0.2; echo 2"],
+                    CURRENT_WORKING_DIRECTORY,
+                )
+                .unwrap(),
+            )
+            .output_grouping(grouping)
+            .payload_message_template("{{name}}:")
+            .execute(buffer.clone());
+        handle.join().unwrap();
+
+        let output = buffer.0.lock().unwrap().clone();
+        // only keep the output lines, the order of the start messages isn't deterministic
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .filter(|line| !line.contains(' '))
+            .map(|line| line.to_string())
+            .collect()
+    }
+
+    /// The output lines of the command `name`, in the order they were written
+    fn lines_of<'a>(lines: &'a [String], name: &str) -> Vec<&'a str> {
+        lines
+            .iter()
+            .filter(|line| line.starts_with(name))
+            .map(|line| line.as_str())
+            .collect()
+    }
+
+    /// Returns true if the lines at the given positions follow each other without lines of other commands in between
+    fn contiguous(lines: &[String], positions: &[&str]) -> bool {
+        let indices: Vec<usize> = positions
+            .iter()
+            .map(|wanted| lines.iter().position(|line| line == wanted).unwrap())
+            .collect();
+        indices.windows(2).all(|pair| pair[1] == pair[0] + 1)
+    }
+
+    #[test]
+    fn groups_output_per_command() {
+        let lines = run_grouped(OutputGrouping::PerCommand);
+        assert_eq!(lines_of(&lines, "a:"), vec!["a:1", "a:2", "a:3"]);
+        assert_eq!(lines_of(&lines, "b:"), vec!["b:1", "b:2"]);
+        assert!(contiguous(&lines, &["a:1", "a:2", "a:3"]));
+        assert!(contiguous(&lines, &["b:1", "b:2"]));
+
+        let lines = run_grouped(OutputGrouping::Interleaved);
+        assert_eq!(lines_of(&lines, "a:"), vec!["a:1", "a:2", "a:3"]);
+        assert_eq!(lines_of(&lines, "b:"), vec!["b:1", "b:2"]);
+    }
+
+    #[test]
+    fn groups_output_in_chunks() {
+        let lines = run_grouped(OutputGrouping::Chunked {
+            lines: 2,
+            idle: Duration::from_secs(10),
+        });
+        assert_eq!(lines_of(&lines, "a:"), vec!["a:1", "a:2", "a:3"]);
+        assert_eq!(lines_of(&lines, "b:"), vec!["b:1", "b:2"]);
+        assert!(contiguous(&lines, &["a:1", "a:2"]));
+        assert!(contiguous(&lines, &["b:1", "b:2"]));
+
+        // the first line of `a` is written once it has been idle, long before `b` is done
+        let lines = run_grouped(OutputGrouping::Chunked {
+            lines: 10,
+            idle: Duration::from_millis(50),
+        });
+        assert_eq!(lines_of(&lines, "a:"), vec!["a:1", "a:2", "a:3"]);
+        let position = |wanted: &str| lines.iter().position(|line| line == wanted).unwrap();
+        assert!(position("a:1") < position("b:2"));
+    }
+
+    #[test]
+    fn errors_dont_end_blocks() {
+        let error = crate::OutputMessagePayload::Error(std::io::Error::other("broken pipe"));
+        assert!(matches!(
+            super::Boundary::of(&error),
+            super::Boundary::Event
+        ));
+    }
+}
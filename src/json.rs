@@ -0,0 +1,403 @@
+use super::time;
+use super::LineEnding;
+use super::OutputMessage;
+use super::OutputMessagePayload;
+use super::ProcessExit;
+use super::Signal;
+use super::StopStep;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::io::BufRead;
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// A single line of the JSON Lines output format.
+/// Only `ts`, `name` and `event` are always present, the other fields depend on the event.
+#[derive(Serialize, Deserialize, Default)]
+struct JsonEvent {
+    /// When the message was captured, as an RFC 3339 timestamp in UTC
+    ts: String,
+    name: String,
+    /// One of `start`, `ready`, `output`, `exit`, `error`, `stopped`, `restarting` or `timed_out`
+    event: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pid: Option<u32>,
+    /// The sequence number of the message among the messages of the command
+    #[serde(default)]
+    seq: u64,
+    /// Milliseconds between executing the commands and capturing the message
+    #[serde(default)]
+    elapsed_ms: u64,
+    /// `stdout` or `stderr` for `output` events
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stream: Option<String>,
+    /// The line of output converted to UTF-8 (invalid sequences are replaced)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    line: Option<String>,
+    /// The raw bytes of the line in base64. Only present if the line isn't valid UTF-8
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    line_base64: Option<String>,
+    /// `lf`, `cr` or `crlf`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    line_ending: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    exit: Option<JsonExit>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// The stop signal or `killed` for `stopped` events
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stop: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    attempt: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    delay_ms: Option<u64>,
+    /// The watched files that changed, for `restarting` events because of changed files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    changed: Option<Vec<String>>,
+}
+
+/// Describes a [ProcessExit]. Exactly one of `code`, `signal` and `stopped` is set
+#[derive(Serialize, Deserialize, Default)]
+struct JsonExit {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    code: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    signal: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    core_dumped: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stopped: Option<i32>,
+}
+
+impl From<ProcessExit> for JsonExit {
+    fn from(exit: ProcessExit) -> Self {
+        match exit {
+            ProcessExit::Exited(code) => JsonExit {
+                code: Some(code),
+                ..JsonExit::default()
+            },
+            ProcessExit::Signaled(signal, core_dumped) => JsonExit {
+                signal: Some(signal),
+                core_dumped: Some(core_dumped),
+                ..JsonExit::default()
+            },
+            ProcessExit::Stopped(signal) => JsonExit {
+                stopped: Some(signal),
+                ..JsonExit::default()
+            },
+        }
+    }
+}
+
+impl JsonExit {
+    fn to_exit(&self) -> Option<ProcessExit> {
+        match (self.code, self.signal, self.stopped) {
+            (Some(code), _, _) => Some(ProcessExit::Exited(code)),
+            (_, Some(signal), _) => Some(ProcessExit::Signaled(
+                signal,
+                self.core_dumped.unwrap_or(false),
+            )),
+            (_, _, Some(signal)) => Some(ProcessExit::Stopped(signal)),
+            _ => None,
+        }
+    }
+}
+
+/// Writes every message on the channel as a line of JSON. See [OutputFormat::JsonLines](crate::OutputFormat::JsonLines)
+pub fn process_channel<W>(chan: &std::sync::mpsc::Receiver<OutputMessage>, mut writer: W)
+where
+    W: Write,
+{
+    for message in chan {
+        let mut line = match serde_json::to_vec(&to_event(message)) {
+            Ok(line) => line,
+            Err(_) => continue,
+        };
+        line.push(b'\n');
+        let _ = writer.write_all(&line);
+    }
+}
+
+fn to_event(message: OutputMessage) -> JsonEvent {
+    let mut event = JsonEvent {
+        ts: time::rfc3339(message.time),
+        name: message.name,
+        pid: message.pid,
+        seq: message.sequence,
+        elapsed_ms: message.elapsed.as_millis() as u64,
+        ..JsonEvent::default()
+    };
+
+    event.event = match message.message {
+        OutputMessagePayload::Start => "start",
+        OutputMessagePayload::Ready => "ready",
+        OutputMessagePayload::Done(exit) => {
+            event.exit = Some(exit.into());
+            "exit"
+        }
+        OutputMessagePayload::Stdout(ending, bytes) => {
+            set_line(&mut event, "stdout", ending, bytes);
+            "output"
+        }
+        OutputMessagePayload::Stderr(ending, bytes) => {
+            set_line(&mut event, "stderr", ending, bytes);
+            "output"
+        }
+        OutputMessagePayload::Error(e) => {
+            event.error = Some(e.to_string());
+            "error"
+        }
+        OutputMessagePayload::Stopped(step) => {
+            event.stop = Some(match step {
+                StopStep::Signal(signal) => signal.as_str().to_string(),
+                StopStep::Killed => "killed".to_string(),
+            });
+            "stopped"
+        }
+        OutputMessagePayload::Restarting {
+            attempt,
+            delay,
+            changed,
+        } => {
+            event.attempt = Some(attempt);
+            event.delay_ms = Some(delay.as_millis() as u64);
+            event.changed = changed.map(|changed| {
+                changed
+                    .iter()
+                    .map(|path| path.to_string_lossy().into_owned())
+                    .collect()
+            });
+            "restarting"
+        }
+        OutputMessagePayload::TimedOut => "timed_out",
+        OutputMessagePayload::Queued => "queued",
+    }
+    .to_string();
+
+    event
+}
+
+fn set_line(event: &mut JsonEvent, stream: &str, ending: LineEnding, bytes: Vec<u8>) {
+    event.stream = Some(stream.to_string());
+    event.line_ending = Some(
+        match ending {
+            LineEnding::Lf => "lf",
+            LineEnding::Cr => "cr",
+            LineEnding::Crlf => "crlf",
+        }
+        .to_string(),
+    );
+    match String::from_utf8(bytes) {
+        Ok(line) => event.line = Some(line),
+        Err(e) => {
+            let bytes = e.into_bytes();
+            event.line = Some(String::from_utf8_lossy(&bytes).to_string());
+            event.line_base64 = Some(BASE64.encode(&bytes));
+        }
+    }
+}
+
+/// Reads the JSON Lines written by the Writer API with [OutputFormat::JsonLines](crate::OutputFormat::JsonLines) back into [OutputMessage]s.
+/// Requires the `json` feature. Error events become errors of kind [io::ErrorKind::Other] with the original message.
+/// Lines that can't be parsed yield an error of kind [io::ErrorKind::InvalidData]. Empty lines are skipped.
+/// ## Example
+/// ```
+/// use currant::JsonLinesReader;
+///
+/// let log = "{\"ts\":\"2024-01-01T00:00:00.000Z\",\"name\":\"web\",\"event\":\"start\"}\n";
+/// for msg in JsonLinesReader::new(log.as_bytes()) {
+///     println!("{}", msg.unwrap().name);
+/// }
+/// ```
+pub struct JsonLinesReader<R: BufRead> {
+    reader: R,
+}
+
+impl<R: BufRead> JsonLinesReader<R> {
+    /// Construct a reader parsing the lines of `reader`
+    pub fn new(reader: R) -> Self {
+        JsonLinesReader { reader }
+    }
+}
+
+impl<R: BufRead> Iterator for JsonLinesReader<R> {
+    type Item = io::Result<OutputMessage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => return None,
+                Ok(_) if line.trim().is_empty() => continue,
+                Ok(_) => return Some(parse_line(&line)),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+fn parse_line(line: &str) -> io::Result<OutputMessage> {
+    let event: JsonEvent = serde_json::from_str(line).map_err(invalid_data)?;
+    let missing =
+        |field: &str| invalid_data(format!("'{}' event without '{}'", event.event, field));
+
+    let message = match event.event.as_str() {
+        "start" => OutputMessagePayload::Start,
+        "ready" => OutputMessagePayload::Ready,
+        "timed_out" => OutputMessagePayload::TimedOut,
+        "queued" => OutputMessagePayload::Queued,
+        "exit" => OutputMessagePayload::Done(
+            event
+                .exit
+                .as_ref()
+                .and_then(JsonExit::to_exit)
+                .ok_or_else(|| missing("exit"))?,
+        ),
+        "output" => {
+            let bytes = match (&event.line_base64, &event.line) {
+                (Some(encoded), _) => BASE64.decode(encoded).map_err(invalid_data)?,
+                (None, Some(line)) => line.clone().into_bytes(),
+                (None, None) => return Err(missing("line")),
+            };
+            let ending = match event.line_ending.as_deref() {
+                Some("cr") => LineEnding::Cr,
+                Some("crlf") => LineEnding::Crlf,
+                _ => LineEnding::Lf,
+            };
+            match event.stream.as_deref() {
+                Some("stdout") => OutputMessagePayload::Stdout(ending, bytes),
+                Some("stderr") => OutputMessagePayload::Stderr(ending, bytes),
+                _ => return Err(missing("stream")),
+            }
+        }
+        "error" => OutputMessagePayload::Error(io::Error::other(
+            event.error.clone().ok_or_else(|| missing("error"))?,
+        )),
+        "stopped" => OutputMessagePayload::Stopped(match event.stop.as_deref() {
+            Some("killed") => StopStep::Killed,
+            Some(signal) => StopStep::Signal(Signal::from_str(signal).map_err(invalid_data)?),
+            None => return Err(missing("stop")),
+        }),
+        "restarting" => OutputMessagePayload::Restarting {
+            attempt: event.attempt.ok_or_else(|| missing("attempt"))?,
+            delay: Duration::from_millis(event.delay_ms.ok_or_else(|| missing("delay_ms"))?),
+            changed: event
+                .changed
+                .as_ref()
+                .map(|changed| changed.iter().map(PathBuf::from).collect()),
+        },
+        other => return Err(invalid_data(format!("unknown event: {}", other))),
+    };
+
+    Ok(OutputMessage {
+        name: event.name,
+        message,
+        time: time::parse_rfc3339(&event.ts)
+            .ok_or_else(|| invalid_data(format!("invalid timestamp: {}", event.ts)))?,
+        elapsed: Duration::from_millis(event.elapsed_ms),
+        pid: event.pid,
+        sequence: event.seq,
+    })
+}
+
+fn invalid_data<E: ToString>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_line;
+    use super::to_event;
+    use super::JsonLinesReader;
+    use crate::writer_api::SharedBuffer;
+    use crate::Command;
+    use crate::OutputFormat;
+    use crate::OutputMessage;
+    use crate::OutputMessagePayload;
+    use crate::Runner;
+    use crate::WriterCommand;
+    use crate::CURRENT_WORKING_DIRECTORY;
+    use std::path::PathBuf;
+    use std::time::Duration;
+    use std::time::SystemTime;
+
+    #[test]
+    fn round_trips_messages() {
+        let buffer = SharedBuffer::default();
+        let handle = Runner::new()
+            .command(
+                WriterCommand::from_argv(
+                    "test",
+                    "sh",
+                    ["-c", "echo hello; printf 'bad \\377\\n' 1>&2; exit 3"],
+                    CURRENT_WORKING_DIRECTORY,
+                )
+                .unwrap(),
+            )
+            .output_format(OutputFormat::JsonLines)
+            .execute(buffer.clone());
+        handle.join().unwrap();
+
+        let output = buffer.0.lock().unwrap().clone();
+        let first_line = output.split(|b| *b == b'\n').next().unwrap();
+        let first: serde_json::Value = serde_json::from_slice(first_line).unwrap();
+        assert_eq!(first["name"], "test");
+        assert_eq!(first["event"], "start");
+        assert!(first["ts"].as_str().unwrap().ends_with('Z'));
+
+        let mut events = Vec::new();
+        for msg in JsonLinesReader::new(&output[..]) {
+            let msg = msg.unwrap();
+            assert_eq!(msg.name, "test");
+            events.push(match msg.message {
+                OutputMessagePayload::Start => "start".to_string(),
+                OutputMessagePayload::Stdout(_, bytes) => format!("stdout {:?}", bytes),
+                OutputMessagePayload::Stderr(_, bytes) => format!("stderr {:?}", bytes),
+                OutputMessagePayload::Done(exit) => exit.to_string(),
+                _ => "other".to_string(),
+            });
+        }
+        events.sort();
+
+        assert_eq!(
+            events,
+            vec![
+                "exited with code 3".to_string(),
+                "start".to_string(),
+                format!("stderr {:?}", b"bad \xff".to_vec()),
+                format!("stdout {:?}", b"hello".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn round_trips_restarts() {
+        let restart = |changed: Option<Vec<PathBuf>>| OutputMessage {
+            name: "server".to_string(),
+            message: OutputMessagePayload::Restarting {
+                attempt: 0,
+                delay: Duration::ZERO,
+                changed,
+            },
+            time: SystemTime::UNIX_EPOCH,
+            elapsed: Duration::ZERO,
+            pid: None,
+            sequence: 0,
+        };
+
+        for changed in [None, Some(vec![PathBuf::from("src/main.rs")])] {
+            let line = serde_json::to_string(&to_event(restart(changed.clone()))).unwrap();
+            assert_eq!(line.contains("\"changed\""), changed.is_some());
+            match parse_line(&line).unwrap().message {
+                OutputMessagePayload::Restarting {
+                    changed: parsed, ..
+                } => assert_eq!(parsed, changed),
+                _ => panic!("restart didn't round trip"),
+            }
+        }
+    }
+}
@@ -1,5 +1,5 @@
-use std::sync::{mpsc, Arc, Barrier, Mutex};
-use std::thread;
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::time::Duration;
 
 /// Synchronizes threads with a barrier-like effect.
 /// All threads wait at the barrier until any one of the threads unlocks the barrier.
@@ -11,52 +11,106 @@ use std::thread;
 /// This differs from a traditional Barrier in that it isn't based on the number of threads at the barrier but rather a condition.
 /// This differs from a conditional variable (condvar) in that all future threads also are unlocked and not just a one at a time unlock for current threads.
 /// This is more of combination between a condvar and a barrier.
+///
+/// Barriers can be nested (see [KillBarrier::child]) so that a single command can be killed on its own while killing the
+/// parent still kills every command.
 pub struct KillBarrier {
-    kill_chan: mpsc::Sender<()>,
-    lock: Arc<Mutex<()>>,
+    state: Arc<(Mutex<KillState>, Condvar)>,
+    root: Option<Arc<(Mutex<KillState>, Condvar)>>,
+}
+
+struct KillState {
+    killed: bool,
+    children: Vec<Weak<(Mutex<KillState>, Condvar)>>,
 }
 
 impl KillBarrier {
     pub fn new() -> KillBarrier {
-        let start_barrier = Arc::new(Barrier::new(2));
-
-        let sync_lock = Arc::new(Mutex::new(()));
-        let (send, recv) = mpsc::channel();
-
-        let lock_clone = sync_lock.clone();
-        let barrier_clone = start_barrier.clone();
-
-        thread::spawn(move || {
-            let _res = lock_clone.lock();
-            barrier_clone.wait();
-
-            let _ = recv.recv();
-        });
-
-        start_barrier.wait();
+        KillBarrier {
+            state: new_state(false),
+            root: None,
+        }
+    }
 
+    /// Creates a barrier that is thrown together with this one but can also be thrown on its own.
+    /// A child of a barrier that has already been thrown starts out thrown.
+    pub fn child(&self) -> KillBarrier {
+        let (lock, _) = &*self.state;
+        let mut parent = match lock.lock() {
+            Ok(parent) => parent,
+            Err(_) => return KillBarrier::new(),
+        };
+        let state = new_state(parent.killed);
+        parent.children.retain(|child| child.strong_count() > 0);
+        parent.children.push(Arc::downgrade(&state));
         KillBarrier {
-            kill_chan: send,
-            lock: sync_lock,
+            state,
+            root: Some(self.root.clone().unwrap_or_else(|| self.state.clone())),
         }
     }
 
+    /// Blocks until the kill switch is thrown
     pub fn wait(&self) -> Result<(), String> {
-        let lock_res = self.lock.lock();
-        match lock_res {
-            Ok(_) => Ok(()),
-            Err(e) => Err(format!("{}", e)),
+        let (lock, cvar) = &*self.state;
+        let killed = lock.lock().map_err(|e| format!("{}", e))?;
+        let _killed = cvar
+            .wait_while(killed, |state| !state.killed)
+            .map_err(|e| format!("{}", e))?;
+        Ok(())
+    }
+
+    /// Blocks until the kill switch is thrown or `timeout` has elapsed.
+    /// Returns true if the kill switch was thrown
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<bool, String> {
+        let (lock, cvar) = &*self.state;
+        let killed = lock.lock().map_err(|e| format!("{}", e))?;
+        let (killed, _) = cvar
+            .wait_timeout_while(killed, timeout, |state| !state.killed)
+            .map_err(|e| format!("{}", e))?;
+        Ok(killed.killed)
+    }
+
+    /// Returns true if the kill switch has been thrown. Doesn't block
+    pub fn is_killed(&self) -> bool {
+        match self.state.0.lock() {
+            Ok(state) => state.killed,
+            Err(_) => true,
         }
     }
 
+    /// Throws the kill switch of this barrier and all of its children
     pub fn initiate_kill(&self) -> Result<(), String> {
-        let res = self.kill_chan.send(());
+        kill(&self.state)
+    }
 
-        match res {
-            Ok(()) => Ok(()),
-            Err(e) => Err(format!("{}", e)),
-        }
+    /// Throws the kill switch of the outermost barrier that this one was created from, which throws every barrier
+    pub fn initiate_kill_all(&self) -> Result<(), String> {
+        kill(self.root.as_ref().unwrap_or(&self.state))
+    }
+}
+
+fn new_state(killed: bool) -> Arc<(Mutex<KillState>, Condvar)> {
+    Arc::new((
+        Mutex::new(KillState {
+            killed,
+            children: Vec::new(),
+        }),
+        Condvar::new(),
+    ))
+}
+
+fn kill(state: &Arc<(Mutex<KillState>, Condvar)>) -> Result<(), String> {
+    let (lock, cvar) = &**state;
+    let children = {
+        let mut state = lock.lock().map_err(|e| format!("{}", e))?;
+        state.killed = true;
+        cvar.notify_all();
+        state.children.clone()
+    };
+    for child in children.iter().filter_map(Weak::upgrade) {
+        kill(&child)?;
     }
+    Ok(())
 }
 
 impl Default for KillBarrier {
@@ -68,8 +122,8 @@ impl Default for KillBarrier {
 impl Clone for KillBarrier {
     fn clone(&self) -> KillBarrier {
         KillBarrier {
-            kill_chan: self.kill_chan.clone(),
-            lock: self.lock.clone(),
+            state: self.state.clone(),
+            root: self.root.clone(),
         }
     }
 }
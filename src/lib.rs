@@ -1,45 +1,97 @@
 //! Run commands in a concurrant manner
 //! There are three main components to this API:
 //! 1) Channel-based API: a basic API that passes all messages, errors, and statuses to channels that the caller can consume at their leisure.
-//! See [ChannelCommand]
+//!    See [ChannelCommand]
 //! 1) Standard-out based API: an API that prints messages and errors to the console (standard out).
-//! See [ConsoleCommand]
+//!    See [ConsoleCommand]
 //! 1) Writer-based API: similar to the standard-out API but prints to an arbitrary writer (like a log file) instead.
-//! See [WriterCommand]
+//!    See [WriterCommand]
+//!
+//! With the `tokio` feature, the Channel API can also be used asynchronously: the handle is a `Stream` of messages.
+//! See `Runner::execute_async`.
 
+#[cfg(feature = "tokio")]
+mod async_api;
 mod channel_api;
+mod child;
 mod color;
+#[cfg(feature = "config")]
+mod config;
 mod control;
+mod dependency;
+mod exit;
+mod grouping;
+#[cfg(feature = "json")]
+mod json;
 mod kill_barrier;
 mod line_parse;
+mod message;
+mod pipeline;
+mod procfile;
+mod readiness;
+mod restart;
 mod run;
+mod slots;
 mod standard_out_api;
+mod stdio;
 mod template;
+mod time;
+mod watch;
 mod which;
 mod writer_api;
 
 use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fmt;
 use std::io;
 use std::io::Write;
+use std::os::unix::process::CommandExt;
 use std::path::PathBuf;
 use std::process;
 use std::process::ExitStatus;
 use std::sync::mpsc;
-use std::sync::Arc;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Duration;
+use std::time::SystemTime;
 
+#[cfg(feature = "tokio")]
+pub use async_api::AsyncCommandHandle;
 pub use channel_api::ChannelCommand;
+pub use child::StopSequence;
+pub use child::StopStep;
 pub use color::Color;
+#[cfg(feature = "config")]
+pub use config::ConfigFormat;
 pub use control::HandleControl;
 pub use control::Signal;
+pub use dependency::DependencyCondition;
+pub use exit::ExitOutcome;
+pub use exit::ExitPolicy;
+pub use exit::ProcessExit;
+pub use grouping::OutputGrouping;
+#[cfg(feature = "json")]
+pub use json::JsonLinesReader;
 pub use line_parse::LineEnding;
+pub use pipeline::Pipeline;
+pub use procfile::Procfile;
+pub use readiness::ReadinessProbe;
+pub use restart::RestartOn;
+pub use restart::RestartPolicy;
 pub use standard_out_api::parse_command_string;
 pub use standard_out_api::ConsoleCommand;
+pub use stdio::CommandStdin;
+pub use stdio::StdinMode;
+pub use stdio::StdioMode;
+pub use watch::WatchPattern;
+pub use writer_api::OutputFormat;
 pub use writer_api::WriterCommand;
 
 pub const CURRENT_WORKING_DIRECTORY: Option<String> = None;
 
+/// How long watched files have to stay unchanged before a command is restarted, unless set via [Runner::watch_debounce]
+const DEFAULT_WATCH_DEBOUNCE: Duration = Duration::from_millis(200);
+
 /// Error type describing any errors encountered while constructing the command
 #[derive(Debug)]
 pub enum CommandError {
@@ -51,12 +103,78 @@ pub enum CommandError {
     /// Couldn't parse the command line string (when the entire command is provided via [Command::from_string]).
     /// Returns the command line string that couldn't be parsed.
     ParseError(String),
+    /// A command depends on a command name that isn't part of the [Runner].
+    /// Returns the name of the dependent command followed by the unknown dependency name.
+    UnknownDependency(String, String),
+    /// The dependencies between commands form a cycle.
+    /// Returns the names of the commands along the cycle, starting and ending with the same command.
+    DependencyCycle(Vec<String>),
+    /// A pattern (like the regular expression of a [ReadinessProbe::LogLine]) couldn't be compiled.
+    /// Returns the reason the pattern is invalid.
+    InvalidPattern(String),
+    /// The command was found but isn't executable (none of the execute bits are set).
+    /// Returns the path of the file that isn't executable.
+    NotExecutable(String),
+    /// The pipes between the stages of a [Pipeline] couldn't be created.
+    /// Returns the reason.
+    PipeError(String),
+    /// A file (like a [Procfile] or a `.env` file) couldn't be read.
+    /// Returns the path of the file followed by the reason.
+    ReadError(String, String),
+    /// A line of a [Procfile] couldn't be parsed.
+    /// Returns the line number followed by the reason.
+    InvalidProcfile(usize, String),
+    /// A line of a `.env` file couldn't be parsed.
+    /// Returns the line number followed by the reason.
+    InvalidEnvFile(usize, String),
+    /// An instance count of a [Procfile] is invalid (see [Procfile::concurrency_spec]).
+    /// Returns the offending part of the specification or the reason.
+    InvalidConcurrency(String),
+    /// A config file (see `Runner::from_config_file`, which requires the `config` feature) couldn't be loaded.
+    /// Returns the path of the file (`<string>` for configs that didn't come from a file), the line of the offending entry if known, and the reason.
+    InvalidConfig(String, Option<usize>, String),
 }
 
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::EmptyCommand => write!(f, "no command provided"),
+            CommandError::CommandNotFound(cmd) => write!(f, "command not found: {}", cmd),
+            CommandError::ParseError(cmd) => write!(f, "unable to parse command: {}", cmd),
+            CommandError::UnknownDependency(name, dep) => {
+                write!(f, "command '{}' depends on unknown command '{}'", name, dep)
+            }
+            CommandError::DependencyCycle(cycle) => {
+                write!(f, "dependency cycle: {}", cycle.join(" -> "))
+            }
+            CommandError::InvalidPattern(e) => write!(f, "invalid pattern: {}", e),
+            CommandError::NotExecutable(path) => write!(f, "not executable: {}", path),
+            CommandError::PipeError(e) => write!(f, "unable to connect pipeline: {}", e),
+            CommandError::ReadError(path, e) => write!(f, "unable to read {}: {}", path, e),
+            CommandError::InvalidProcfile(line, e) => {
+                write!(f, "invalid Procfile, line {}: {}", line, e)
+            }
+            CommandError::InvalidEnvFile(line, e) => {
+                write!(f, "invalid env file, line {}: {}", line, e)
+            }
+            CommandError::InvalidConcurrency(e) => write!(f, "invalid concurrency: {}", e),
+            CommandError::InvalidConfig(path, Some(line), e) => {
+                write!(f, "invalid config {}, line {}: {}", path, line, e)
+            }
+            CommandError::InvalidConfig(path, None, e) => {
+                write!(f, "invalid config {}: {}", path, e)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
 /// Various options for running commands
 #[derive(Clone)]
 struct Options {
     /// Set what should happen when a command exits with a non-zero exit code.
+    /// Commands can override this with [Command::restart].
     /// See [RestartOptions] for possible values and defaults
     restart: RestartOptions,
     /// Supresses console messages about commands starting (defaults to false).
@@ -68,6 +186,26 @@ struct Options {
     /// Defaults to false (no file handle flags).
     /// If false, all output is dumped to the console (or writer) without these o/e prefixes.
     file_handle_flags: bool,
+    /// How commands are stopped when they get killed. See [StopSequence] for the default.
+    stop_sequence: StopSequence,
+    /// Run every command in its own process group so that signals reach the whole process tree (defaults to false).
+    process_groups: bool,
+    /// LINUX-ONLY: Have the kernel kill every command when this process dies.
+    kill_on_parent_death: bool,
+    /// LINUX-ONLY: Re-parent orphaned descendants of the commands to this process instead of `init`.
+    child_subreaper: bool,
+    /// How the Writer and Standard Out API group the output of concurrent commands (defaults to interleaved).
+    output_grouping: OutputGrouping,
+    /// The maximum number of commands running at the same time (defaults to no limit).
+    max_parallel: Option<usize>,
+    /// How the exit results are reduced to a single outcome (defaults to [ExitPolicy::All]).
+    exit_policy: ExitPolicy,
+    /// Kill the other commands once the exit policy has decided the outcome (defaults to false).
+    kill_when_decided: bool,
+    /// How long the files watched by a command have to stay unchanged before it is restarted.
+    watch_debounce: Duration,
+    /// Wait for changes to the watched files of commands that are done instead of leaving them done for good (defaults to false).
+    watch_after_exit: bool,
 }
 
 /// An Internal class that isn't really meant to be used externally.
@@ -77,23 +215,96 @@ struct Options {
 pub struct InnerCommand {
     name: String,
     command: String,
+    resolved_path: PathBuf,
     args: Vec<String>,
     cur_dir: Option<PathBuf>,
     env: HashMap<String, String>,
+    stdout: StdioMode,
+    stderr: StdioMode,
+    stdin: StdinMode,
+    depends_on: Vec<(String, DependencyCondition)>,
+    readiness: Option<ReadinessProbe>,
+    stop_sequence: Option<StopSequence>,
+    restart: Option<RestartOptions>,
+    timeout: Option<Duration>,
+    priority: i32,
+    /// Identifies the pipeline of the runner that the command is a stage of
+    pipeline: Option<usize>,
+    watch: Vec<WatchPattern>,
 }
 
-impl From<InnerCommand> for process::Command {
-    fn from(cmd: InnerCommand) -> Self {
-        let mut command_process = process::Command::new(cmd.command);
-        command_process.args(cmd.args);
-        if cmd.cur_dir.is_some() {
-            command_process.current_dir(cmd.cur_dir.unwrap());
+impl InnerCommand {
+    /// Creates the command, resolving its executable without running it.
+    /// `path_var` is the `PATH` it is looked up in (the `PATH` of the current process if `None`).
+    fn new(
+        name: String,
+        command: String,
+        args: Vec<String>,
+        cur_dir: Option<PathBuf>,
+        path_var: Option<&OsStr>,
+    ) -> Result<Self, CommandError> {
+        let resolved_path = which::resolve(&command, &cur_dir, path_var)?;
+        Ok(InnerCommand {
+            name,
+            command,
+            resolved_path,
+            args,
+            cur_dir,
+            env: HashMap::new(),
+            stdout: StdioMode::Piped,
+            stderr: StdioMode::Piped,
+            stdin: StdinMode::Inherit,
+            depends_on: Vec::new(),
+            readiness: None,
+            stop_sequence: None,
+            restart: None,
+            timeout: None,
+            priority: 0,
+            pipeline: None,
+            watch: Vec::new(),
+        })
+    }
+
+    /// Returns the executable to spawn: the one resolved when the command was constructed or, if the command sets its own
+    /// `PATH` via [Command::env], the one found in that `PATH`. The working directory can't change after construction.
+    fn resolve(&self) -> Result<PathBuf, CommandError> {
+        match self.env.get("PATH") {
+            Some(path_var) => {
+                which::resolve(&self.command, &self.cur_dir, Some(OsStr::new(path_var)))
+            }
+            None => Ok(self.resolved_path.clone()),
+        }
+    }
+
+    /// Builds the process for the resolved `program`, keeping the command as its `argv[0]`
+    fn into_process(self, program: PathBuf) -> process::Command {
+        let mut command_process = process::Command::new(program);
+        command_process.arg0(self.command);
+        command_process.args(self.args);
+        if let Some(dir) = self.cur_dir {
+            command_process.current_dir(dir);
         }
-        command_process.envs(cmd.env);
-        command_process.stdout(process::Stdio::piped());
+        command_process.envs(self.env);
+        command_process.stdout(self.stdout.to_stdio());
+        command_process.stderr(self.stderr.to_stdio());
 
         command_process
     }
+
+    /// Returns true if standard out and standard error should share a single pipe
+    fn is_output_merged(&self) -> bool {
+        self.stdout.is_merged() || self.stderr.is_merged()
+    }
+}
+
+impl From<InnerCommand> for process::Command {
+    /// If the executable can't be resolved, the process is built with the bare command name and spawning it fails
+    fn from(cmd: InnerCommand) -> Self {
+        let program = cmd
+            .resolve()
+            .unwrap_or_else(|_| PathBuf::from(&cmd.command));
+        cmd.into_process(program)
+    }
 }
 
 /// Common trait expressing all the various operations you can do with a `Command`
@@ -112,9 +323,23 @@ where
     /// Provide a mutable reference to the wrapped [InnerCommand] that was inserted via [insert_command](Command::insert_command)
     fn get_command_mut(&mut self) -> &mut InnerCommand;
 
+    /// The color of the command for APIs that color their output (see [ConsoleCommand::color]).
+    /// This is used when a command is added to a running handle (see [ControlledCommandHandle::add_command]). The default is `None`.
+    fn get_color(&self) -> Option<Color> {
+        None
+    }
+
+    /// Sets the color of the command for APIs that color their output (see [ConsoleCommand::color]).
+    /// This is used when commands are loaded from a config file (see `Runner::from_config_file`). The default ignores the color.
+    fn set_color(&mut self, _color: Color) {}
+
     /// Construct a command from a command name (human readable command name), command executable, a list of arguments, and a directory to run the command in.
     /// None for `cur_dir` will default to the current working directory (or use [CURRENT_WORKING_DIRECTORY] constant).
     ///
+    /// The executable is resolved right away without running it: names containing a `/` are paths relative to `cur_dir`,
+    /// other names are looked up in the `PATH`. The command keeps `command` as its `argv[0]`.
+    /// Returns [CommandError::CommandNotFound] or [CommandError::NotExecutable] if it can't be resolved. If the command sets
+    /// its own `PATH` via [Command::env], the executable is looked up again in that `PATH` whenever the command is started.
     /// If the command cannot be constructed for various reasons, an `Err(CommandError)` is returned. See [CommandError] for more info on errors.
     /// ## Example
     /// ```
@@ -137,22 +362,14 @@ where
         ArgType: Into<String>,
         Cmds: IntoIterator<Item = ArgType>,
     {
-        let name = name.into();
-        let cmd = command.into();
-        let dir = cur_dir.map(|d| d.into());
-        check_command(&cmd, &dir)?;
-
-        if name.is_empty() || cmd.is_empty() {
-            return Err(CommandError::EmptyCommand);
-        }
         let converted_args = args.into_iter().map(|s| s.into()).collect::<Vec<String>>();
-        Ok(Self::insert_command(InnerCommand {
-            name,
-            command: cmd,
-            args: converted_args,
-            cur_dir: dir,
-            env: HashMap::new(),
-        }))
+        command_from_argv(
+            name.into(),
+            command.into(),
+            converted_args,
+            cur_dir.map(|d| d.into()),
+            None,
+        )
     }
 
     /// Construct a command from a command name (human readable command name), a full cli string, and a current working directory.
@@ -160,7 +377,7 @@ where
     ///
     /// The API will parse the cli string into the executable and arguments automatically.
     /// The API supports some features like quotes but not advanced features like pipes or logical operators.
-    /// For those advanced features, you will need to format the command as a subshell (via `sh -c "..."`).
+    /// For pipes, see [Pipeline]. For the other advanced features, you will need to format the command as a subshell (via `sh -c "..."`).
     /// If the command cannot be constructed for various reasons, an `Err(CommandError)` is returned. See [CommandError] for more info on errors.
     /// ## Example
     /// ```
@@ -182,20 +399,11 @@ where
         C: Into<String>,
         D: Into<PathBuf>,
     {
-        let (command, args) = parse_command_string(command_string)?;
-        let dir = cur_dir.map(|d| d.into());
-        check_command(&command, &dir)?;
-
-        Ok(Self::insert_command(InnerCommand {
-            name: name.into(),
-            command,
-            args,
-            cur_dir: dir,
-            env: HashMap::new(),
-        }))
+        command_from_string(name.into(), command_string, cur_dir.map(|d| d.into()), None)
     }
 
     /// Sets environment variables for this command.
+    /// Setting `PATH` also changes where the executable is looked up: it is looked up again whenever the command is started.
     /// ## Example
     /// ```
     /// use currant::ConsoleCommand;
@@ -213,6 +421,183 @@ where
         self.get_command_mut().env.insert(key.into(), val.into());
         self
     }
+
+    /// Sets how standard out of this command is handled. Defaults to [StdioMode::Piped].
+    /// See [StdioMode] for the possible values.
+    /// ## Example
+    /// ```
+    /// use currant::ConsoleCommand;
+    /// use currant::Command;
+    /// use currant::StdioMode;
+    /// use currant::CURRENT_WORKING_DIRECTORY;
+    ///
+    /// let mut cmd = ConsoleCommand::from_string("test_cmd", "ls -la .", CURRENT_WORKING_DIRECTORY).unwrap();
+    /// cmd.stdout(StdioMode::Null);
+    /// ```
+    fn stdout(&mut self, mode: StdioMode) -> &mut Self {
+        self.get_command_mut().stdout = mode;
+        self
+    }
+
+    /// Sets how standard error of this command is handled. Defaults to [StdioMode::Piped].
+    /// Use [StdioMode::Merged] to receive standard error interleaved with standard out in the order it was written.
+    /// ## Example
+    /// ```
+    /// use currant::ConsoleCommand;
+    /// use currant::Command;
+    /// use currant::StdioMode;
+    /// use currant::CURRENT_WORKING_DIRECTORY;
+    ///
+    /// let mut cmd = ConsoleCommand::from_string("test_cmd", "ls -la .", CURRENT_WORKING_DIRECTORY).unwrap();
+    /// cmd.stderr(StdioMode::Merged);
+    /// ```
+    fn stderr(&mut self, mode: StdioMode) -> &mut Self {
+        self.get_command_mut().stderr = mode;
+        self
+    }
+
+    /// Sets what this command reads from standard in. Defaults to [StdinMode::Inherit].
+    /// See [StdinMode] for the possible values.
+    /// ## Example
+    /// ```
+    /// use currant::ConsoleCommand;
+    /// use currant::Command;
+    /// use currant::StdinMode;
+    /// use currant::CURRENT_WORKING_DIRECTORY;
+    ///
+    /// let mut cmd = ConsoleCommand::from_string("test_cmd", "wc -l", CURRENT_WORKING_DIRECTORY).unwrap();
+    /// cmd.stdin(StdinMode::Bytes(b"one\ntwo\n".to_vec()));
+    /// ```
+    fn stdin(&mut self, mode: StdinMode) -> &mut Self {
+        self.get_command_mut().stdin = mode;
+        self
+    }
+
+    /// Declares that this command may only start once the command named `name` meets `condition`.
+    /// Can be called multiple times to depend on several commands.
+    /// The [Runner] validates the dependency graph when it executes (see [Runner::validate]).
+    /// If a dependency can never meet its condition (for example it exits with a failure status), this command is never started
+    /// and an [OutputMessagePayload::Error] is reported for it instead.
+    /// ## Example
+    /// ```
+    /// use currant::{ConsoleCommand, Command, DependencyCondition, Runner, CURRENT_WORKING_DIRECTORY};
+    ///
+    /// let handle = Runner::new()
+    ///     .command(ConsoleCommand::from_string("migrate", "echo migrating", CURRENT_WORKING_DIRECTORY).unwrap())
+    ///     .command(
+    ///         ConsoleCommand::from_string("server", "echo serving", CURRENT_WORKING_DIRECTORY)
+    ///             .unwrap()
+    ///             .depends_on("migrate", DependencyCondition::ExitedSuccessfully),
+    ///     )
+    ///     .execute();
+    /// handle.join().unwrap();
+    /// ```
+    fn depends_on<S: Into<String>>(
+        &mut self,
+        name: S,
+        condition: DependencyCondition,
+    ) -> &mut Self {
+        self.get_command_mut()
+            .depends_on
+            .push((name.into(), condition));
+        self
+    }
+
+    /// Sets the probe deciding when this command is ready. See [ReadinessProbe] for the possible probes.
+    /// Without a probe, a command is ready as soon as its process has been spawned (and no [OutputMessagePayload::Ready] message is sent).
+    /// ## Example
+    /// ```
+    /// use currant::{ConsoleCommand, Command, ReadinessProbe, CURRENT_WORKING_DIRECTORY};
+    ///
+    /// let mut cmd = ConsoleCommand::from_string("server", "echo listening", CURRENT_WORKING_DIRECTORY).unwrap();
+    /// cmd.ready_when(ReadinessProbe::log_line("listening").unwrap());
+    /// ```
+    fn ready_when(&mut self, probe: ReadinessProbe) -> &mut Self {
+        self.get_command_mut().readiness = Some(probe);
+        self
+    }
+
+    /// Sets how this command is stopped when it gets killed, overriding [Runner::stop_sequence].
+    /// ## Example
+    /// ```
+    /// use currant::{ConsoleCommand, Command, Signal, StopSequence, CURRENT_WORKING_DIRECTORY};
+    /// use std::time::Duration;
+    ///
+    /// let mut cmd = ConsoleCommand::from_string("server", "sleep 10", CURRENT_WORKING_DIRECTORY).unwrap();
+    /// cmd.stop_sequence(StopSequence::new(Signal::SIGINT, Duration::from_secs(10)));
+    /// ```
+    fn stop_sequence(&mut self, sequence: StopSequence) -> &mut Self {
+        self.get_command_mut().stop_sequence = Some(sequence);
+        self
+    }
+
+    /// Sets what happens when this command exits, overriding [Runner::restart] for this command only.
+    /// This allows long-lived servers that restart to run next to one-shot steps that kill everything when they fail.
+    /// ## Example
+    /// ```
+    /// use currant::{ConsoleCommand, Command, RestartOptions, Runner, CURRENT_WORKING_DIRECTORY};
+    ///
+    /// let handle = Runner::new()
+    ///     .command(
+    ///         ConsoleCommand::from_string("build", "echo building", CURRENT_WORKING_DIRECTORY)
+    ///             .unwrap()
+    ///             .restart(RestartOptions::Kill),
+    ///     )
+    ///     .command(ConsoleCommand::from_string("server", "echo serving", CURRENT_WORKING_DIRECTORY).unwrap())
+    ///     .restart(RestartOptions::Restart)
+    ///     .execute();
+    /// handle.join().unwrap();
+    /// ```
+    fn restart(&mut self, restart_opt: RestartOptions) -> &mut Self {
+        self.get_command_mut().restart = Some(restart_opt);
+        self
+    }
+
+    /// Limits how long a single run of this command may take.
+    /// Once the timeout elapses, an [OutputMessagePayload::TimedOut] message is sent and the command is stopped with its
+    /// [StopSequence]. The run counts as a failure, so it may be restarted according to the restart behavior, and every restart
+    /// gets the full timeout again.
+    /// ## Example
+    /// ```
+    /// use currant::{ConsoleCommand, Command, CURRENT_WORKING_DIRECTORY};
+    /// use std::time::Duration;
+    ///
+    /// let mut cmd = ConsoleCommand::from_string("tests", "cargo test", CURRENT_WORKING_DIRECTORY).unwrap();
+    /// cmd.timeout(Duration::from_secs(600));
+    /// ```
+    fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.get_command_mut().timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the priority of this command in the queue of [Runner::max_parallel]. The default is 0.
+    /// When a slot frees up, the waiting command with the highest priority is started first. Commands with the same priority
+    /// are started in the order they were added to the runner. Without a limit on parallel commands, this has no effect.
+    fn priority(&mut self, priority: i32) -> &mut Self {
+        self.get_command_mut().priority = priority;
+        self
+    }
+
+    /// Adds a glob pattern of files to watch. Can be called several times to watch more files.
+    /// Once matching files are created, changed or deleted and no further change arrives for [Runner::watch_debounce],
+    /// the command is stopped with its [StopSequence] and spawned again, announced by an [OutputMessagePayload::Restarting]
+    /// message listing the changed files. A command that is done on its own (for example a build that failed) is done for good,
+    /// unless it should wait for the next change (see [Runner::watch_after_exit]).
+    ///
+    /// LINUX-ONLY: files are watched with inotify. The directories are watched when the command is first spawned, so the leading
+    /// directories of the pattern (the ones without wildcards) have to exist by then. Stages of a pipeline aren't restarted.
+    /// ## Example
+    /// ```
+    /// use currant::{ConsoleCommand, Command, WatchPattern, CURRENT_WORKING_DIRECTORY};
+    ///
+    /// let mut cmd = ConsoleCommand::from_string("server", "cargo run", CURRENT_WORKING_DIRECTORY).unwrap();
+    /// cmd.watch(WatchPattern::new("src/**/*.rs").unwrap())
+    ///     .watch(WatchPattern::new("Cargo.toml").unwrap());
+    /// ```
+    fn watch(&mut self, pattern: WatchPattern) -> &mut Self {
+        self.get_command_mut().watch.push(pattern);
+        self
+    }
 }
 
 /// Represents output from a command
@@ -222,33 +607,96 @@ pub struct OutputMessage {
     pub name: String,
     /// The message payload. See [OutputMessagePayload] for more info
     pub message: OutputMessagePayload,
+    /// The wall clock time when the message was captured
+    pub time: SystemTime,
+    /// The monotonic time between executing the commands and capturing the message.
+    /// Unlike `time`, this isn't affected by changes to the system clock, so it can be used to order the messages of all commands.
+    pub elapsed: Duration,
+    /// The pid of the process the message belongs to. `None` if the command couldn't be spawned (or hasn't been spawned yet)
+    pub pid: Option<u32>,
+    /// The position of the message among all messages of the same command, starting at 0 and counting across restarts
+    pub sequence: u64,
 }
 
 /// The payload of an output message
 pub enum OutputMessagePayload {
     /// Command has started execution
     Start,
-    /// Command has exited. Returns the exit status (if available) of the command
-    Done(Option<i32>),
-    /// A single line of standard out formatted as a byte vector. The line ending is included in the enum but not in the byte vector
+    /// The command's readiness probe has passed (see [Command::ready_when]). Sent at most once per run of the command.
+    Ready,
+    /// Command has exited. Tells whether it exited on its own or was terminated by a signal (see [ProcessExit])
+    Done(ProcessExit),
+    /// A single line of standard out formatted as a byte vector. The line ending is included in the enum but not in the byte vector.
+    /// When the command merges its output (see [StdioMode::Merged]), standard error lines are reported here too.
     Stdout(line_parse::LineEnding, Vec<u8>),
     /// A single line of standard error formatted as a byte vector. The line ending is included in the enum but not in the byte vector
     Stderr(line_parse::LineEnding, Vec<u8>),
     /// An error has occurred with the command (usually a malformed command or I/O error). This doesn't include commands that fail via exit status.
     /// That is reported via [OutputMessagePayload::Done].
     Error(io::Error),
+    /// The command was stopped because it got killed. Reports which step of the [StopSequence] ended the process.
+    /// Sent right before the corresponding [OutputMessagePayload::Done].
+    Stopped(StopStep),
+    /// The command is about to be restarted according to its [RestartPolicy] or because files it watches changed (see [Command::watch]).
+    /// `attempt` counts the consecutive restarts (starting at 1) and `delay` is how long currant waits before respawning the command.
+    /// For restarts because of changed files, `changed` lists the files that changed, `attempt` is 0 and `delay` is zero: the command
+    /// is spawned again right away (a command that was still running has been stopped by then). It is `None` for all other restarts.
+    Restarting {
+        attempt: u32,
+        delay: Duration,
+        changed: Option<Vec<PathBuf>>,
+    },
+    /// The command has to wait for a free slot before it can start (see [Runner::max_parallel]).
+    /// [OutputMessagePayload::Start] follows once it gets one. Commands that can start right away don't send this message.
+    Queued,
+    /// The command ran longer than its timeout (see [Command::timeout]) and is about to be stopped.
+    /// The [OutputMessagePayload::Stopped] and [OutputMessagePayload::Done] messages follow once it has exited.
+    TimedOut,
 }
 
-/// Exit status tuple. This string is the human-readable command name, the exit status is the exit
-/// status code of the process if available
-pub type ExitResult = (String, Option<ExitStatus>);
+/// The result of a command once it is done for good (after any restarts)
+#[derive(Clone, Debug)]
+pub struct ExitResult {
+    /// The human-readable command name
+    pub name: String,
+    /// The exit status of the last run of the command, if it could be spawned
+    pub status: Option<ExitStatus>,
+    /// True if the last run was stopped because it ran longer than its timeout (see [Command::timeout])
+    pub timed_out: bool,
+    /// When the command was done for good, measured from the moment the commands were executed
+    pub elapsed: Duration,
+    /// True if the command was killed rather than being done on its own: along with all other commands
+    /// (like under [RestartOptions::Kill]) or on its own (see [CommandHandle::remove_command])
+    pub killed: bool,
+}
+
+impl ExitResult {
+    fn new(name: String, status: Option<ExitStatus>, timed_out: bool, killed: bool) -> Self {
+        ExitResult {
+            name,
+            status,
+            timed_out,
+            elapsed: Duration::ZERO,
+            killed,
+        }
+    }
+
+    /// How the last run of the command ended, if it could be spawned. See [ProcessExit]
+    pub fn exit(&self) -> Option<ProcessExit> {
+        self.status.map(ProcessExit::from)
+    }
+}
 
-/// A handle so the caller can control various aspects of the running commands
+/// A handle so the caller can control various aspects of the running commands.
+/// Dropping the handle kills all commands and waits for them to exit, unless it has been joined or [detached](CommandHandle::detach).
 pub struct CommandHandle {
-    handle: thread::JoinHandle<Vec<ExitResult>>,
+    handle: run::Supervisor,
     channel: mpsc::Receiver<OutputMessage>,
     kill_trigger: kill_barrier::KillBarrier,
-    pids: Vec<Arc<(String, Mutex<Option<u32>>)>>,
+    pids: control::PidTable,
+    tracker: dependency::DependencyTracker,
+    commands: run::CommandSet,
+    exit_policy: ExitPolicy,
 }
 
 impl CommandHandle {
@@ -262,6 +710,34 @@ impl CommandHandle {
             .map_err(|_| "Thread panic'ed before exit".to_string())
     }
 
+    /// Like [CommandHandle::join] but reduces the exit results to a single outcome according to the [exit policy](Runner::exit_policy).
+    /// ## Example
+    /// ```
+    /// use currant::{ChannelCommand, Command, ExitPolicy, Runner, CURRENT_WORKING_DIRECTORY};
+    ///
+    /// let outcome = Runner::new()
+    ///     .command(ChannelCommand::from_string("server", "sleep 10", CURRENT_WORKING_DIRECTORY).unwrap())
+    ///     .command(ChannelCommand::from_string("tests", "true", CURRENT_WORKING_DIRECTORY).unwrap())
+    ///     .exit_policy(ExitPolicy::Command("tests".to_string()))
+    ///     .kill_when_decided(true)
+    ///     .execute()
+    ///     .join_outcome()
+    ///     .unwrap();
+    /// assert!(outcome.success);
+    /// assert_eq!(outcome.code, 0);
+    /// ```
+    pub fn join_outcome(self) -> Result<ExitOutcome, String> {
+        let policy = self.exit_policy.clone();
+        self.join().map(|results| policy.outcome(&results))
+    }
+
+    /// Let the commands keep running without this handle.
+    /// By default, dropping a [CommandHandle] kills all commands and waits for them to exit.
+    /// After detaching, the commands are left running in the background and can't be joined or killed anymore.
+    pub fn detach(self) {
+        self.handle.detach();
+    }
+
     /// returns a reference to the output channel (only in the channel based API).
     /// This channel will give the caller access to the output and status messages from the child commands.
     /// See [OutputMessage] for details on the channel payload.
@@ -269,7 +745,9 @@ impl CommandHandle {
         &self.channel
     }
 
-    /// kills all children processes without waiting for them to complete
+    /// kills all children processes without waiting for them to complete.
+    /// Each command is stopped according to its [StopSequence]: the stop signal is sent first, followed by `SIGKILL` if the
+    /// process doesn't exit in time.
     pub fn kill(&self) {
         let _ = self.kill_trigger.initiate_kill();
     }
@@ -280,6 +758,55 @@ impl CommandHandle {
     pub fn get_signaler(&self) -> control::HandleControl {
         HandleControl::new(self.pids.clone(), self.kill_trigger.clone())
     }
+
+    /// Block the current thread until the command named `cmd_name` is ready.
+    /// A command is ready once its readiness probe passes (see [Command::ready_when]) or, without a probe, once it has started.
+    /// Returns an error message if the command doesn't exist, exits without ever becoming ready, or all commands are killed while waiting.
+    pub fn wait_until_ready(&self, cmd_name: &str) -> Result<(), String> {
+        wait_until_ready(&self.pids, &self.tracker, &self.kill_trigger, cmd_name)
+    }
+
+    /// Start another command alongside the running ones. Its messages are sent on the same channel.
+    /// Returns an error message if a command with the same name is still running, the command depends on a command that
+    /// isn't part of the handle, or the commands are already done (or killed).
+    ///
+    /// The command is configured with the options of the [Runner] the handle was created from, it shows up in the
+    /// [HandleControl] and its [ExitResult] is returned from [CommandHandle::join] after the results of the earlier commands.
+    /// ## Example
+    /// ```
+    /// use currant::{ChannelCommand, Command, Runner, CURRENT_WORKING_DIRECTORY};
+    ///
+    /// let handle = Runner::new()
+    ///     .command(ChannelCommand::from_string("server", "sleep 1", CURRENT_WORKING_DIRECTORY).unwrap())
+    ///     .execute();
+    ///
+    /// handle
+    ///     .add_command(ChannelCommand::from_string("worker", "echo working", CURRENT_WORKING_DIRECTORY).unwrap())
+    ///     .unwrap();
+    /// handle.remove_command("server").unwrap();
+    ///
+    /// let results = handle.join().unwrap();
+    /// assert_eq!(results.len(), 2);
+    /// ```
+    pub fn add_command<T: AsRef<ChannelCommand>>(&self, cmd: T) -> Result<(), String> {
+        self.commands.add(cmd.as_ref().get_command().clone(), || {})
+    }
+
+    /// Stop the command named `cmd_name` according to its [StopSequence] without waiting for it to exit.
+    /// The command isn't restarted and, unlike a failure under [RestartOptions::Kill], doesn't take the other commands down.
+    /// It is removed from the [HandleControl] right away, which frees up its name for [CommandHandle::add_command].
+    /// Its [ExitResult] is still returned from [CommandHandle::join].
+    /// Returns an error message if no command with that name is part of the handle.
+    pub fn remove_command(&self, cmd_name: &str) -> Result<(), String> {
+        self.commands.remove(cmd_name)
+    }
+
+    /// Returns a writer that feeds standard in of the command named `cmd_name`, which has to use [StdinMode::Interactive].
+    /// Writes fail until the command has been spawned, so wait for it first (for example with [CommandHandle::wait_until_ready]).
+    /// See [CommandStdin] for more details.
+    pub fn stdin(&self, cmd_name: &str) -> Result<CommandStdin, String> {
+        command_stdin(&self.pids, cmd_name)
+    }
 }
 
 /// Iterates over the messages on the channel. Yields values of [OutputMessage]
@@ -302,11 +829,16 @@ impl Iterator for &CommandHandle {
 /// provides a handle to the running children process for the Writer and Console API.
 /// This differs from [CommandHandle] in that it doesn't provide any reference to the output channel since
 /// that is managed internally by currant.
+/// Like [CommandHandle], dropping the handle kills all commands unless it has been joined or [detached](ControlledCommandHandle::detach).
 pub struct ControlledCommandHandle {
     supervisor: thread::JoinHandle<()>,
-    handle: thread::JoinHandle<Vec<ExitResult>>,
+    handle: run::Supervisor,
     kill_trigger: kill_barrier::KillBarrier,
-    pids: Vec<Arc<(String, Mutex<Option<u32>>)>>,
+    pids: control::PidTable,
+    tracker: dependency::DependencyTracker,
+    commands: run::CommandSet,
+    colors: Option<Arc<Mutex<HashMap<String, Color>>>>,
+    exit_policy: ExitPolicy,
 }
 
 impl ControlledCommandHandle {
@@ -320,7 +852,20 @@ impl ControlledCommandHandle {
             .map_err(|_| "thread panic'ed before exit".to_string())
     }
 
+    /// Block the thread until all processes have completed and reduce their exit results to a single outcome.
+    /// See [CommandHandle::join_outcome] for more details.
+    pub fn join_outcome(self) -> Result<ExitOutcome, String> {
+        let policy = self.exit_policy.clone();
+        self.join().map(|results| policy.outcome(&results))
+    }
+
+    /// Let the commands keep running without this handle. See [CommandHandle::detach] for more details.
+    pub fn detach(self) {
+        self.handle.detach();
+    }
+
     /// Kill all children processes without waiting for them to complete. See [CommandHandle::kill] for more details.
+    /// Each command is stopped according to its [StopSequence].
     pub fn kill(&self) {
         let _ = self.kill_trigger.initiate_kill();
     }
@@ -331,17 +876,109 @@ impl ControlledCommandHandle {
     pub fn get_signaler(&self) -> control::HandleControl {
         HandleControl::new(self.pids.clone(), self.kill_trigger.clone())
     }
+
+    /// Block the current thread until the command named `cmd_name` is ready. See [CommandHandle::wait_until_ready] for more details.
+    pub fn wait_until_ready(&self, cmd_name: &str) -> Result<(), String> {
+        wait_until_ready(&self.pids, &self.tracker, &self.kill_trigger, cmd_name)
+    }
+
+    /// Start another command alongside the running ones. See [CommandHandle::add_command] for more details.
+    /// For the Console API, the command gets its own color (see [ConsoleCommand::color]). Commands with [Color::Random]
+    /// get a color that is as distinct as possible from the colors of the running commands.
+    pub fn add_command<C: Command>(&self, cmd: &C) -> Result<(), String> {
+        let inner_command = cmd.get_command().clone();
+        let name = inner_command.name.clone();
+        self.commands.add(inner_command, || {
+            if let Some(colors) = &self.colors {
+                if let Ok(mut colors) = colors.lock() {
+                    let color = match cmd.get_color().unwrap_or(Color::Random) {
+                        Color::Random => color::distinct_color(colors.values()),
+                        color => color,
+                    };
+                    colors.insert(name, color);
+                }
+            }
+        })
+    }
+
+    /// Stop the command named `cmd_name` and remove it from the handle. See [CommandHandle::remove_command] for more details.
+    pub fn remove_command(&self, cmd_name: &str) -> Result<(), String> {
+        self.commands.remove(cmd_name)
+    }
+
+    /// Returns a writer that feeds standard in of the command named `cmd_name`. See [CommandHandle::stdin] for more details.
+    pub fn stdin(&self, cmd_name: &str) -> Result<CommandStdin, String> {
+        command_stdin(&self.pids, cmd_name)
+    }
+}
+
+fn command_stdin(pids: &control::PidTable, cmd_name: &str) -> Result<CommandStdin, String> {
+    let pids = pids
+        .lock()
+        .map_err(|_| "Unable to acquire poisoned lock for pidlist".to_string())?;
+    let entry = pids
+        .iter()
+        .find(|pid_arc| pid_arc.name == cmd_name)
+        .ok_or_else(|| format!("process named: '{}' not found", cmd_name))?;
+    if !entry.interactive {
+        return Err(format!(
+            "process named: '{}' doesn't have an interactive standard in",
+            cmd_name
+        ));
+    }
+    Ok(CommandStdin::new(entry.clone()))
+}
+
+fn wait_until_ready(
+    pids: &control::PidTable,
+    tracker: &dependency::DependencyTracker,
+    kill_trigger: &kill_barrier::KillBarrier,
+    cmd_name: &str,
+) -> Result<(), String> {
+    let known = match pids.lock() {
+        Ok(pids) => pids.iter().any(|pid_arc| pid_arc.name == cmd_name),
+        Err(_) => false,
+    };
+    if !known {
+        return Err(format!("process named: '{}' not found", cmd_name));
+    }
+
+    let condition = [(cmd_name.to_string(), DependencyCondition::Ready)];
+    if tracker.wait_for(&condition, kill_trigger)? {
+        Ok(())
+    } else {
+        Err(format!(
+            "processes were killed before '{}' became ready",
+            cmd_name
+        ))
+    }
 }
 
 /// An enum to tell currant what to do when a process exits with _nonzero_ (AKA failure) status
+/// or, with [RestartOptions::KillOnExit], when it exits at all
 #[derive(Clone)]
 pub enum RestartOptions {
     /// (DEFAULT): Let the failed process die (no-restart) and let all other processes continue as normal.
     Continue,
-    /// Restart the failed process
+    /// Restart the failed process using the default [RestartPolicy] (exponential backoff starting at 100 milliseconds, no attempt limit)
     Restart,
+    /// Restart the process according to the given [RestartPolicy]
+    RestartWith(RestartPolicy),
     /// kill all children when any one process fails
     Kill,
+    /// Kill all children when any one process exits, whether it failed or not.
+    /// Useful to tear everything down once a single command (like a test client next to a server) is done
+    KillOnExit,
+}
+
+impl RestartOptions {
+    fn restart_policy(&self) -> Option<RestartPolicy> {
+        match self {
+            RestartOptions::Restart => Some(RestartPolicy::default()),
+            RestartOptions::RestartWith(policy) => Some(policy.clone()),
+            RestartOptions::Continue | RestartOptions::Kill | RestartOptions::KillOnExit => None,
+        }
+    }
 }
 
 /// A structure that represents a set of commands to run.
@@ -371,9 +1008,21 @@ pub enum RestartOptions {
 /// ```
 pub struct Runner<C: Command> {
     commands: Vec<C>,
+    pipelines: usize,
     restart: RestartOptions,
     quiet: bool,
     file_handle_flags: bool,
+    stop_sequence: StopSequence,
+    process_groups: bool,
+    kill_on_parent_death: bool,
+    child_subreaper: bool,
+    output_grouping: OutputGrouping,
+    max_parallel: Option<usize>,
+    exit_policy: ExitPolicy,
+    kill_when_decided: bool,
+    watch_debounce: Duration,
+    watch_after_exit: bool,
+    output_format: OutputFormat,
     start_message_template: String,
     done_message_template: String,
     payload_message_template: String,
@@ -391,9 +1040,21 @@ impl<C: Command> Runner<C> {
     pub fn new() -> Self {
         Runner {
             commands: Vec::new(),
+            pipelines: 0,
             restart: RestartOptions::Continue,
             quiet: false,
             file_handle_flags: false,
+            stop_sequence: StopSequence::default(),
+            process_groups: false,
+            kill_on_parent_death: false,
+            child_subreaper: false,
+            output_grouping: OutputGrouping::Interleaved,
+            max_parallel: None,
+            exit_policy: ExitPolicy::All,
+            kill_when_decided: false,
+            watch_debounce: DEFAULT_WATCH_DEBOUNCE,
+            watch_after_exit: false,
+            output_format: OutputFormat::Text,
             start_message_template: "{{begin_color}}SYSTEM: starting process {{name}}{{reset_color}}"
                 .to_string(),
             done_message_template:
@@ -411,7 +1072,21 @@ impl<C: Command> Runner<C> {
         self
     }
 
+    /// Add the stages of a pipeline as commands, connecting standard out of every stage to standard in of the next stage.
+    /// See [Pipeline] for more info.
+    pub fn pipeline<T: AsRef<Pipeline<C>>>(&mut self, pipeline: T) -> &mut Self {
+        let id = self.pipelines;
+        self.pipelines += 1;
+        for stage in pipeline.as_ref().stages() {
+            let mut stage = stage.clone();
+            stage.get_command_mut().pipeline = Some(id);
+            self.commands.push(stage);
+        }
+        self
+    }
+
     /// Set the restart behavior. The default is [RestartOptions::Continue].
+    /// This applies to every command that doesn't set its own behavior via [Command::restart].
     /// See [RestartOptions] for more info.
     pub fn restart(&mut self, restart_opt: RestartOptions) -> &mut Self {
         self.restart = restart_opt;
@@ -437,6 +1112,103 @@ impl<C: Command> Runner<C> {
         self
     }
 
+    /// Set how commands are stopped when they get killed (via [CommandHandle::kill], [HandleControl::kill_all] or [RestartOptions::Kill]).
+    /// The default sends `SIGTERM` and escalates to `SIGKILL` after 5 seconds. See [StopSequence] for more info.
+    /// Individual commands can override this via [Command::stop_sequence].
+    pub fn stop_sequence(&mut self, sequence: StopSequence) -> &mut Self {
+        self.stop_sequence = sequence;
+        self
+    }
+
+    /// Set the maximum number of commands that run at the same time. By default, every command is started right away.
+    /// With a limit, the remaining commands are queued (see [OutputMessagePayload::Queued]) and started as running commands
+    /// finish for good, by [priority](Command::priority) and then in the order they were added. A limit of 0 is treated as 1.
+    ///
+    /// A command keeps its slot across restarts. Commands only join the queue once their dependencies are met
+    /// (see [Command::depends_on]), so waiting on a dependency doesn't take up a slot.
+    /// All stages of a [Pipeline] share a single slot, so a pipeline with more stages than the limit still runs.
+    pub fn max_parallel(&mut self, max_parallel: usize) -> &mut Self {
+        self.max_parallel = Some(max_parallel);
+        self
+    }
+
+    /// Set how the exit results are reduced to a single [ExitOutcome] by `join_outcome` (see [CommandHandle::join_outcome]).
+    /// The default is [ExitPolicy::All], where every command has to succeed.
+    pub fn exit_policy(&mut self, policy: ExitPolicy) -> &mut Self {
+        self.exit_policy = policy;
+        self
+    }
+
+    /// Set whether the other commands are killed as soon as the [exit policy](Runner::exit_policy) has decided the outcome,
+    /// like `concurrently --success` combined with `--kill-others`. The default is `false`.
+    /// Under [ExitPolicy::First], the first command to be done for good kills the others, under [ExitPolicy::Command]
+    /// the named command does and under [ExitPolicy::All] the first command to fail does. [ExitPolicy::Last] never kills.
+    /// Commands that are killed or removed don't decide anything.
+    pub fn kill_when_decided(&mut self, kill_when_decided_opt: bool) -> &mut Self {
+        self.kill_when_decided = kill_when_decided_opt;
+        self
+    }
+
+    /// Set how long the files watched by a command (see [Command::watch]) have to stay unchanged before the command is restarted,
+    /// so that saving many files at once only restarts it once. The default is 200 milliseconds.
+    pub fn watch_debounce(&mut self, debounce: Duration) -> &mut Self {
+        self.watch_debounce = debounce;
+        self
+    }
+
+    /// Set whether commands that watch files (see [Command::watch]) and are done on their own, like a build that failed, wait for
+    /// the next change and are spawned again then. The default is `false`, in which case only running commands are reloaded.
+    /// A waiting command counts as exited for [Command::depends_on] and frees its slot of [Runner::max_parallel], but it is only
+    /// done for good once it gets killed. So it doesn't show up in the results or decide the [ExitPolicy] until then.
+    pub fn watch_after_exit(&mut self, watch_after_exit_opt: bool) -> &mut Self {
+        self.watch_after_exit = watch_after_exit_opt;
+        self
+    }
+
+    /// Set whether every command runs in its own process group. The default is `false`: commands stay in the process group
+    /// of this process, so a Ctrl-C in the terminal reaches them just like it reaches this process, but only the direct child
+    /// of a command is signalled when it gets killed or via [HandleControl::signal_one] and [HandleControl::signal_all].
+    /// Commands like `sh -c "npm run dev"` spawn grandchildren. In its own process group, the whole process tree of a command is
+    /// stopped when it gets killed, and [HandleControl::signal_one] and [HandleControl::signal_all] signal the whole tree.
+    ///
+    /// Note that commands in their own process group don't receive signals that the terminal sends to the foreground
+    /// process group (like `SIGINT` on Ctrl-C), and this library doesn't forward them. An application that turns this on has to
+    /// handle `SIGINT` and `SIGTERM` itself and kill the commands (for example via [CommandHandle::get_signaler]), otherwise
+    /// they keep running after it exits.
+    pub fn process_groups(&mut self, process_groups_opt: bool) -> &mut Self {
+        self.process_groups = process_groups_opt;
+        self
+    }
+
+    /// LINUX-ONLY: Set whether every command is killed with `SIGKILL` when this process dies. The default is `false`.
+    /// Dropping the handle already kills the commands, but this also covers panics that abort and the process being killed
+    /// with `SIGKILL`, where no cleanup code gets to run. Only the direct child of every command is signalled by the kernel.
+    /// This has no effect on other platforms.
+    pub fn kill_on_parent_death(&mut self, kill_on_parent_death_opt: bool) -> &mut Self {
+        self.kill_on_parent_death = kill_on_parent_death_opt;
+        self
+    }
+
+    /// LINUX-ONLY: Set whether this process becomes a child subreaper when the commands are executed. The default is `false`.
+    /// As a subreaper, descendants of the commands that are orphaned (for example, the grandchildren left behind by a shell
+    /// that got killed) are re-parented to this process instead of `init`. This is a process-wide setting which stays in
+    /// effect after the commands exit, and the application becomes responsible for reaping the orphans it inherits.
+    /// If the kernel refuses, the commands still run and each of them reports the failure as an [OutputMessagePayload::Error].
+    /// This has no effect on other platforms.
+    pub fn child_subreaper(&mut self, child_subreaper_opt: bool) -> &mut Self {
+        self.child_subreaper = child_subreaper_opt;
+        self
+    }
+
+    /// Set how the output of concurrent commands is grouped. This only affects the console and writer APIs.
+    /// The default is [OutputGrouping::Interleaved], where every line is printed as soon as it arrives.
+    /// [OutputGrouping::PerCommand] prints the output of every command as one block when it exits, which keeps the logs
+    /// of build steps readable. See [OutputGrouping] for more info.
+    pub fn output_grouping(&mut self, grouping: OutputGrouping) -> &mut Self {
+        self.output_grouping = grouping;
+        self
+    }
+
     /// Set the start message template. This only affects the console and writer APIs.
     /// Default is:
     ///
@@ -446,6 +1218,9 @@ impl<C: Command> Runner<C> {
     /// * `{{name}}`: the name of the process
     /// * `{{begin_color}}`: color the following text with the color of the command (ignored for APIs without color)
     /// * `{{reset_color}}`: resets the color back to terminal default
+    /// * `{{time}}`: the wall clock time the message was captured, as an RFC 3339 timestamp in UTC (like `2024-01-01T12:30:00.000Z`)
+    /// * `{{elapsed}}`: the time between executing the commands and capturing the message, in seconds (like `12.345s`)
+    /// * `{{pid}}`: the pid of the process the message belongs to. Changes to `(none)` when there is no process.
     pub fn start_message_template<S: Into<String>>(&mut self, template: S) -> &mut Self {
         self.start_message_template = template.into();
         self
@@ -458,7 +1233,11 @@ impl<C: Command> Runner<C> {
     /// `{{begin_color}}{{name}}:{{reset_color}} process exited with status: {{status_code}}`
     ///
     /// Additional interpolations:
-    /// * `{{status_code}}`: the exit status of the command. Changes to `(none)` when there is no exit status.
+    /// * `{{exit}}`: how the command ended in words, like `exited with code 1` or `killed by SIGTERM` (see [ProcessExit]).
+    ///   Unlike `{{status_code}}`, it also describes commands that were terminated by a signal, for example
+    ///   `{{begin_color}}{{name}}:{{reset_color}} process {{exit}}`.
+    /// * `{{status_code}}`: the exit code of the command. Changes to `(none)` when the command was terminated by a signal.
+    /// * `{{signal}}`: the name of the signal that terminated the command, like `SIGTERM`. Changes to `(none)` when the command exited on its own.
     pub fn done_message_template<S: Into<String>>(&mut self, template: S) -> &mut Self {
         self.done_message_template = template.into();
         self
@@ -473,7 +1252,7 @@ impl<C: Command> Runner<C> {
     ///
     /// Additional interpolations:
     /// * `{{handle_flag}}`: the handle flag for which file handle the payload is on. `(o)` for stdout and `(e)` for standard error.
-    /// If [Runner::should_show_file_handle] is set to false (default), these interpolations will be set to empty string.
+    ///   If [Runner::should_show_file_handle] is set to false (default), these interpolations will be set to empty string.
     pub fn payload_message_template<S: Into<String>>(&mut self, template: S) -> &mut Self {
         self.payload_message_template = template.into();
         self
@@ -492,11 +1271,34 @@ impl<C: Command> Runner<C> {
         self
     }
 
+    /// Checks that the commands can be run together.
+    /// Every name passed to [Command::depends_on] must belong to a command in this runner and the dependencies may not form a cycle.
+    /// `execute` performs the same check. When it fails there, no command is started and each command reports the problem as an
+    /// [OutputMessagePayload::Error] instead.
+    pub fn validate(&self) -> Result<(), CommandError> {
+        let actual_cmds: Vec<InnerCommand> = self
+            .commands
+            .iter()
+            .map(|c| c.get_command().clone())
+            .collect();
+        dependency::validate(&actual_cmds).map(|_| ())
+    }
+
     fn to_options(&self) -> Options {
         Options {
             restart: self.restart.clone(),
             quiet: self.quiet,
             file_handle_flags: self.file_handle_flags,
+            stop_sequence: self.stop_sequence.clone(),
+            process_groups: self.process_groups,
+            kill_on_parent_death: self.kill_on_parent_death,
+            child_subreaper: self.child_subreaper,
+            output_grouping: self.output_grouping,
+            max_parallel: self.max_parallel,
+            exit_policy: self.exit_policy.clone(),
+            kill_when_decided: self.kill_when_decided,
+            watch_debounce: self.watch_debounce,
+            watch_after_exit: self.watch_after_exit,
         }
     }
 
@@ -518,6 +1320,13 @@ impl Runner<ChannelCommand> {
 }
 
 impl Runner<WriterCommand> {
+    /// Set the format messages are written in. The default is [OutputFormat::Text].
+    /// See [OutputFormat] for the available formats.
+    pub fn output_format(&mut self, format: OutputFormat) -> &mut Self {
+        self.output_format = format;
+        self
+    }
+
     /// Execute the commands using the Writer API. The writer must be provided here. The `Runner` must be constructed with `WriterCommand`s
     pub fn execute<W: Write + Send + 'static>(&mut self, writer: W) -> ControlledCommandHandle {
         writer_api::run_commands_writer(self, writer)
@@ -531,6 +1340,36 @@ impl Runner<ConsoleCommand> {
     }
 }
 
+/// Constructs a command like [Command::from_argv], looking the executable up in `path_var` (the `PATH` of the current process if `None`).
+/// Loaders use this to resolve the executable in the `PATH` they set on the command.
+pub(crate) fn command_from_argv<C: Command>(
+    name: String,
+    command: String,
+    args: Vec<String>,
+    cur_dir: Option<PathBuf>,
+    path_var: Option<&OsStr>,
+) -> Result<C, CommandError> {
+    if name.is_empty() || command.is_empty() {
+        return Err(CommandError::EmptyCommand);
+    }
+    Ok(C::insert_command(InnerCommand::new(
+        name, command, args, cur_dir, path_var,
+    )?))
+}
+
+/// Constructs a command like [Command::from_string], looking the executable up in `path_var` (the `PATH` of the current process if `None`)
+pub(crate) fn command_from_string<C: Command, S: Into<String>>(
+    name: String,
+    command_string: S,
+    cur_dir: Option<PathBuf>,
+    path_var: Option<&OsStr>,
+) -> Result<C, CommandError> {
+    let (command, args) = parse_command_string(command_string)?;
+    Ok(C::insert_command(InnerCommand::new(
+        name, command, args, cur_dir, path_var,
+    )?))
+}
+
 fn run_commands<C: Command>(runner: &Runner<C>) -> CommandHandle {
     let actual_cmds = runner
         .commands
@@ -540,17 +1379,17 @@ fn run_commands<C: Command>(runner: &Runner<C>) -> CommandHandle {
     run::run_commands_internal(actual_cmds, runner.to_options())
 }
 
-fn check_command(exec_name: &str, dir: &Option<PathBuf>) -> Result<(), CommandError> {
-    if which::exec_exists(exec_name, dir) {
-        Ok(())
-    } else {
-        Err(CommandError::CommandNotFound(exec_name.to_string()))
-    }
-}
-
 #[cfg(test)]
 mod test {
+    use crate::ChannelCommand;
     use crate::Command;
+    use crate::OutputMessagePayload;
+    use crate::RestartOptions;
+    use crate::Runner;
+    use crate::StdioMode;
+    use std::time::Duration;
+    use std::time::Instant;
+    use std::time::SystemTime;
 
     #[test]
     fn command_not_found() {
@@ -568,6 +1407,52 @@ mod test {
         }
     }
 
+    #[test]
+    fn resolves_with_own_path() {
+        let dir = std::env::temp_dir().join(format!("currant_own_path_{}", std::process::id()));
+        let empty_dir = dir.join("empty");
+        std::fs::create_dir_all(&empty_dir).unwrap();
+        let script = dir.join("true");
+        std::fs::write(&script, "#!/bin/sh\necho found\n").unwrap();
+        std::fs::set_permissions(&script, std::os::unix::fs::PermissionsExt::from_mode(0o755))
+            .unwrap();
+
+        // both resolve in the PATH of this process, but run with a PATH of their own
+        let mut found =
+            ChannelCommand::from_string("found", "true", super::CURRENT_WORKING_DIRECTORY).unwrap();
+        found.env("PATH", dir.to_str().unwrap());
+        let mut missing =
+            ChannelCommand::from_string("missing", "true", super::CURRENT_WORKING_DIRECTORY)
+                .unwrap();
+        missing.env("PATH", empty_dir.to_str().unwrap());
+
+        let handle = Runner::new().command(&found).command(&missing).execute();
+        let mut lines = Vec::new();
+        let mut errors = Vec::new();
+        for message in handle.get_output_channel().iter() {
+            match message.message {
+                OutputMessagePayload::Stdout(_, line) => lines.push(line),
+                OutputMessagePayload::Error(e) => errors.push(e),
+                _ => {}
+            }
+        }
+        let results = handle.join().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(lines, vec![b"found".to_vec()]);
+        assert!(results[0].status.unwrap().success());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind(), std::io::ErrorKind::NotFound);
+        match errors[0]
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<super::CommandError>())
+        {
+            Some(super::CommandError::CommandNotFound(name)) => assert_eq!(name, "true"),
+            _ => panic!("missing executable wasn't reported as CommandNotFound"),
+        }
+        assert!(results[1].status.is_none());
+    }
+
     #[test]
     fn command_empty() {
         let cmd = super::ConsoleCommand::from_string("test", "", super::CURRENT_WORKING_DIRECTORY);
@@ -577,4 +1462,242 @@ mod test {
             _ => panic!("empty command didn't error out"),
         }
     }
+
+    #[test]
+    fn stderr_is_captured() {
+        let handle = Runner::new()
+            .command(
+                ChannelCommand::from_argv(
+                    "test",
+                    "sh",
+                    ["-c", "echo out; echo err 1>&2"],
+                    super::CURRENT_WORKING_DIRECTORY,
+                )
+                .unwrap(),
+            )
+            .execute();
+
+        let mut stdout_lines = Vec::new();
+        let mut stderr_lines = Vec::new();
+        for msg in &handle {
+            match msg.message {
+                OutputMessagePayload::Stdout(_, bytes) => stdout_lines.push(bytes),
+                OutputMessagePayload::Stderr(_, bytes) => stderr_lines.push(bytes),
+                _ => {}
+            }
+        }
+        handle.join().unwrap();
+
+        assert_eq!(stdout_lines, vec![b"out".to_vec()]);
+        assert_eq!(stderr_lines, vec![b"err".to_vec()]);
+    }
+
+    #[test]
+    fn dropping_handle_kills_commands() {
+        let mut handle = Runner::new()
+            .command(
+                ChannelCommand::from_argv(
+                    "test",
+                    "sh",
+                    ["-c", "echo $$; exec sleep 30"],
+                    super::CURRENT_WORKING_DIRECTORY,
+                )
+                .unwrap(),
+            )
+            .execute();
+
+        let pid = handle
+            .find_map(|msg| match msg.message {
+                OutputMessagePayload::Stdout(_, bytes) => {
+                    String::from_utf8_lossy(&bytes).parse::<i32>().ok()
+                }
+                _ => None,
+            })
+            .unwrap();
+        drop(handle);
+
+        let still_running = nix::sys::signal::kill(nix::unistd::Pid::from_raw(pid), None);
+        assert_eq!(still_running, Err(nix::errno::Errno::ESRCH));
+    }
+
+    #[test]
+    fn adds_and_removes_commands() {
+        let started = Instant::now();
+        let handle = Runner::new()
+            .command(
+                ChannelCommand::from_string("server", "sleep 30", super::CURRENT_WORKING_DIRECTORY)
+                    .unwrap(),
+            )
+            .command(
+                ChannelCommand::from_string("other", "sleep 1", super::CURRENT_WORKING_DIRECTORY)
+                    .unwrap(),
+            )
+            .restart(RestartOptions::Kill)
+            .execute();
+
+        let worker =
+            ChannelCommand::from_string("worker", "echo hi", super::CURRENT_WORKING_DIRECTORY)
+                .unwrap();
+        handle.add_command(&worker).unwrap();
+        assert!(handle.add_command(&worker).is_err());
+        assert!(handle
+            .add_command(
+                ChannelCommand::from_string("late", "true", super::CURRENT_WORKING_DIRECTORY)
+                    .unwrap()
+                    .depends_on("missing", crate::DependencyCondition::Started)
+            )
+            .is_err());
+
+        handle.wait_until_ready("server").unwrap();
+        handle.remove_command("server").unwrap();
+        assert!(handle.remove_command("server").is_err());
+        assert!(handle
+            .get_signaler()
+            .signal_one("server", crate::Signal::SIGTERM)
+            .is_err());
+
+        let lines: Vec<_> = handle
+            .get_output_channel()
+            .iter()
+            .filter_map(|msg| match msg.message {
+                OutputMessagePayload::Stdout(_, bytes) => Some((msg.name, bytes)),
+                _ => None,
+            })
+            .collect();
+        assert!(handle.add_command(&worker).is_err());
+        let results = handle.join().unwrap();
+
+        assert_eq!(lines, vec![("worker".to_string(), b"hi".to_vec())]);
+        let names: Vec<_> = results.iter().map(|result| result.name.as_str()).collect();
+        assert_eq!(names, vec!["server", "other", "worker"]);
+        // removing the server neither restarted it nor killed the others
+        assert!(!results[0].status.unwrap().success());
+        assert!(results[1].status.unwrap().success());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn command_times_out() {
+        let started = Instant::now();
+        let handle = Runner::new()
+            .command(
+                ChannelCommand::from_string("slow", "sleep 5", super::CURRENT_WORKING_DIRECTORY)
+                    .unwrap()
+                    .timeout(Duration::from_millis(200)),
+            )
+            .command(
+                ChannelCommand::from_string("fast", "true", super::CURRENT_WORKING_DIRECTORY)
+                    .unwrap()
+                    .timeout(Duration::from_secs(5)),
+            )
+            .execute();
+
+        let mut timeouts = Vec::new();
+        for msg in &handle {
+            if let OutputMessagePayload::TimedOut = msg.message {
+                timeouts.push(msg.name);
+            }
+        }
+        let results = handle.join().unwrap();
+
+        assert_eq!(timeouts, vec!["slow"]);
+        assert!(results[0].timed_out);
+        assert!(!results[0].status.unwrap().success());
+        assert!(!results[1].timed_out);
+        assert!(results[1].status.unwrap().success());
+        assert!(started.elapsed() < Duration::from_secs(3));
+    }
+
+    #[test]
+    fn clean_exit_after_timeout_fails() {
+        let started = Instant::now();
+        let results = Runner::new()
+            .command(
+                ChannelCommand::from_argv(
+                    "graceful",
+                    "sh",
+                    ["-c", "trap 'exit 0' TERM; sleep 10"],
+                    super::CURRENT_WORKING_DIRECTORY,
+                )
+                .unwrap()
+                .timeout(Duration::from_millis(200)),
+            )
+            .command(
+                ChannelCommand::from_string("other", "sleep 10", super::CURRENT_WORKING_DIRECTORY)
+                    .unwrap(),
+            )
+            .restart(RestartOptions::Kill)
+            // the trap only runs once sleep is gone
+            .process_groups(true)
+            .execute()
+            .join()
+            .unwrap();
+
+        assert!(results[0].timed_out);
+        assert!(results[0].status.unwrap().success());
+        assert!(!results[1].status.unwrap().success());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn messages_are_stamped() {
+        let before = SystemTime::now();
+        let handle = Runner::new()
+            .command(
+                ChannelCommand::from_argv(
+                    "test",
+                    "sh",
+                    ["-c", "echo $$; echo $$ 1>&2"],
+                    super::CURRENT_WORKING_DIRECTORY,
+                )
+                .unwrap(),
+            )
+            .execute();
+
+        let messages: Vec<_> = handle.get_output_channel().iter().collect();
+        handle.join().unwrap();
+
+        let sequences: Vec<_> = messages.iter().map(|msg| msg.sequence).collect();
+        assert_eq!(sequences, vec![0, 1, 2, 3]);
+        for msg in &messages {
+            assert!(msg.time >= before);
+            if let OutputMessagePayload::Stdout(_, bytes) | OutputMessagePayload::Stderr(_, bytes) =
+                &msg.message
+            {
+                assert_eq!(String::from_utf8_lossy(bytes), msg.pid.unwrap().to_string());
+            }
+        }
+        assert!(messages.windows(2).all(|w| w[0].elapsed <= w[1].elapsed));
+    }
+
+    #[test]
+    fn merged_output_keeps_order() {
+        let handle = Runner::new()
+            .command(
+                ChannelCommand::from_argv(
+                    "test",
+                    "sh",
+                    ["-c", "echo one; echo two 1>&2; echo three"],
+                    super::CURRENT_WORKING_DIRECTORY,
+                )
+                .unwrap()
+                .stderr(StdioMode::Merged),
+            )
+            .execute();
+
+        let mut lines = Vec::new();
+        for msg in &handle {
+            match msg.message {
+                OutputMessagePayload::Stdout(_, bytes) => lines.push(bytes),
+                OutputMessagePayload::Stderr(_, _) => panic!("merged output reported on stderr"),
+                _ => {}
+            }
+        }
+        handle.join().unwrap();
+
+        assert_eq!(
+            lines,
+            vec![b"one".to_vec(), b"two".to_vec(), b"three".to_vec()]
+        );
+    }
 }
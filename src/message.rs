@@ -0,0 +1,67 @@
+use super::OutputMessage;
+use super::OutputMessagePayload;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+
+struct SenderState {
+    sequence: u64,
+    pid: Option<u32>,
+}
+
+/// Sends the messages of a single command.
+/// Every message is stamped with the capture time, the pid of the command's current process and the next sequence number.
+/// Clones share the sequence so that the messages of all threads working for the command are numbered in the order they are sent.
+#[derive(Clone)]
+pub struct MessageSender {
+    name: String,
+    chan: mpsc::Sender<OutputMessage>,
+    started: Instant,
+    state: Arc<Mutex<SenderState>>,
+}
+
+impl MessageSender {
+    /// `started` is the moment the commands were executed, which the elapsed time of every message is measured from
+    pub fn new(name: &str, chan: mpsc::Sender<OutputMessage>, started: Instant) -> Self {
+        MessageSender {
+            name: name.to_string(),
+            chan,
+            started,
+            state: Arc::new(Mutex::new(SenderState {
+                sequence: 0,
+                pid: None,
+            })),
+        }
+    }
+
+    /// Sets the pid that following messages are stamped with
+    pub fn set_pid(&self, pid: Option<u32>) {
+        if let Ok(mut state) = self.state.lock() {
+            state.pid = pid;
+        }
+    }
+
+    /// The time since the commands were executed
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn send(&self, message: OutputMessagePayload) {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return,
+        };
+        let msg = OutputMessage {
+            name: self.name.clone(),
+            message,
+            time: SystemTime::now(),
+            elapsed: self.started.elapsed(),
+            pid: state.pid,
+            sequence: state.sequence,
+        };
+        state.sequence += 1;
+        let _ = self.chan.send(msg);
+    }
+}
@@ -0,0 +1,211 @@
+use super::Command;
+use super::ExitResult;
+use super::InnerCommand;
+use super::ProcessExit;
+use std::io;
+
+/// A chain of commands where standard out of every stage is connected to standard in of the next stage, like `a | b | c` in a shell.
+/// Add it to a [Runner](crate::Runner) via [Runner::pipeline](crate::Runner::pipeline).
+///
+/// Unlike a pipe inside `sh -c`, every stage is a regular command of the runner: it keeps its own name, reports its own
+/// [Start](crate::OutputMessagePayload::Start) and [Done](crate::OutputMessagePayload::Done) messages as well as its standard error,
+/// and can be signalled or removed on its own. Only standard out of the last stage is reported as output.
+/// If a stage merges its output (see [StdioMode::Merged](crate::StdioMode::Merged)), its standard error goes down the pipe as well, like `|&`.
+///
+/// The connection between the stages only exists for a single run, so stages are never restarted
+/// ([RestartOptions::Kill](crate::RestartOptions::Kill) and [RestartOptions::KillOnExit](crate::RestartOptions::KillOnExit) still apply). Standard in of every stage but the first is the pipe,
+/// whatever [StdinMode](crate::StdinMode) it was given. Under [Runner::max_parallel](crate::Runner::max_parallel), all stages share a single slot:
+/// the stages can only make progress together, so they are started together as well.
+/// ## Example
+/// ```
+/// use currant::{ChannelCommand, Command, OutputMessagePayload, Pipeline, Runner, CURRENT_WORKING_DIRECTORY};
+///
+/// let pipeline = Pipeline::new()
+///     .stage(ChannelCommand::from_string("list", "printf 'b\na\n'", CURRENT_WORKING_DIRECTORY).unwrap())
+///     .stage(ChannelCommand::from_string("sort", "sort", CURRENT_WORKING_DIRECTORY).unwrap())
+///     .clone();
+///
+/// let handle = Runner::new().pipeline(&pipeline).execute();
+/// for msg in &handle {
+///     if let OutputMessagePayload::Stdout(_, bytes) = msg.message {
+///         println!("{}: {}", msg.name, String::from_utf8_lossy(&bytes));
+///     }
+/// }
+///
+/// let results = handle.join().unwrap();
+/// assert!(pipeline.exit(&results).unwrap().success());
+/// ```
+#[derive(Clone)]
+pub struct Pipeline<C: Command> {
+    stages: Vec<C>,
+}
+
+impl<C: Command> Default for Pipeline<C> {
+    fn default() -> Self {
+        Pipeline::new()
+    }
+}
+
+impl<C: Command> Pipeline<C> {
+    /// Instantiate a new pipeline without any stages
+    pub fn new() -> Self {
+        Pipeline { stages: Vec::new() }
+    }
+
+    /// Add a stage to the end of the pipeline
+    pub fn stage<T: AsRef<C>>(&mut self, cmd: T) -> &mut Self {
+        self.stages.push(cmd.as_ref().clone());
+        self
+    }
+
+    pub(crate) fn stages(&self) -> &[C] {
+        &self.stages
+    }
+
+    /// Combines the results of the stages like `set -o pipefail` does in a shell.
+    /// Returns how the last stage that failed ended or, if every stage succeeded, how the last stage ended.
+    /// Returns `None` if the results of a stage are missing from `results` or a stage couldn't be spawned.
+    pub fn exit(&self, results: &[ExitResult]) -> Option<ProcessExit> {
+        let mut exits = Vec::new();
+        for stage in &self.stages {
+            let name = &stage.get_command().name;
+            let result = results.iter().find(|result| &result.name == name)?;
+            exits.push(result.exit()?);
+        }
+
+        exits
+            .iter()
+            .rev()
+            .find(|exit| !exit.success())
+            .or_else(|| exits.last())
+            .copied()
+    }
+}
+
+impl<C: Command> AsRef<Pipeline<C>> for Pipeline<C> {
+    fn as_ref(&self) -> &Pipeline<C> {
+        self
+    }
+}
+
+/// The ends of the pipes that a single stage is connected to
+#[derive(Default)]
+pub struct StageIo {
+    pub stdin: Option<io::PipeReader>,
+    pub stdout: Option<io::PipeWriter>,
+}
+
+/// Creates the pipes between consecutive commands of the same pipeline.
+/// Returns the pipe ends for every command, which are empty for commands outside of pipelines.
+pub fn connect(commands: &[InnerCommand]) -> io::Result<Vec<StageIo>> {
+    let mut stages: Vec<StageIo> = commands.iter().map(|_| StageIo::default()).collect();
+    for idx in 1..commands.len() {
+        let pipeline = commands[idx].pipeline;
+        if pipeline.is_some() && pipeline == commands[idx - 1].pipeline {
+            let (reader, writer) = io::pipe()?;
+            stages[idx - 1].stdout = Some(writer);
+            stages[idx].stdin = Some(reader);
+        }
+    }
+    Ok(stages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pipeline;
+    use crate::ChannelCommand;
+    use crate::Command;
+    use crate::OutputMessagePayload;
+    use crate::ProcessExit;
+    use crate::Runner;
+    use crate::CURRENT_WORKING_DIRECTORY;
+
+    fn stage(name: &str, script: &str) -> ChannelCommand {
+        ChannelCommand::from_argv(name, "sh", ["-c", script], CURRENT_WORKING_DIRECTORY).unwrap()
+    }
+
+    #[test]
+    fn connects_stages() {
+        let pipeline = Pipeline::new()
+            .stage(stage(
+                "produce",
+                "printf 'b\\na\\nc\\n'; echo produced 1>&2",
+            ))
+            .stage(stage("sort", "sort"))
+            .stage(stage("count", "wc -l | tr -d ' '; exit 3"))
+            .clone();
+        let handle = Runner::new()
+            .pipeline(&pipeline)
+            .command(stage("other", "true"))
+            .execute();
+
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        let mut done = Vec::new();
+        for msg in &handle {
+            match msg.message {
+                OutputMessagePayload::Stdout(_, bytes) => stdout.push((msg.name, bytes)),
+                OutputMessagePayload::Stderr(_, bytes) => stderr.push((msg.name, bytes)),
+                OutputMessagePayload::Done(_) => done.push(msg.name),
+                _ => {}
+            }
+        }
+        let results = handle.join().unwrap();
+        done.sort();
+
+        assert_eq!(stdout, vec![("count".to_string(), b"3".to_vec())]);
+        assert_eq!(stderr, vec![("produce".to_string(), b"produced".to_vec())]);
+        assert_eq!(done, vec!["count", "other", "produce", "sort"]);
+        assert_eq!(pipeline.exit(&results), Some(ProcessExit::Exited(3)));
+    }
+
+    #[test]
+    fn shares_a_slot() {
+        // far more output than fits into a pipe, so the stages have to run at the same time
+        let pipeline = Pipeline::new()
+            .stage(stage("produce", "seq 1 200000"))
+            .stage(stage("copy", "cat"))
+            .stage(stage("count", "wc -l | tr -d ' '"))
+            .clone();
+        let handle = Runner::new()
+            .pipeline(&pipeline)
+            .command(stage("other", "sleep 0.2"))
+            .max_parallel(1)
+            .execute();
+
+        let signaler = handle.get_signaler();
+        let watchdog = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_secs(10));
+            let _ = signaler.kill_all();
+        });
+        let mut stdout = Vec::new();
+        let mut queued = Vec::new();
+        for msg in &handle {
+            match msg.message {
+                OutputMessagePayload::Stdout(_, bytes) => stdout.push(bytes),
+                OutputMessagePayload::Queued => queued.push(msg.name),
+                _ => {}
+            }
+        }
+        let results = handle.join().unwrap();
+
+        assert!(!watchdog.is_finished(), "the pipeline got stuck");
+        assert_eq!(stdout, vec![b"200000".to_vec()]);
+        assert_eq!(pipeline.exit(&results), Some(ProcessExit::Exited(0)));
+        // the stages may be queued for a moment until the first one takes the slot
+        assert!(queued.contains(&"other".to_string()));
+    }
+
+    #[test]
+    fn fails_like_pipefail() {
+        let pipeline = Pipeline::new()
+            .stage(stage("first", "echo hi; exit 2"))
+            .stage(stage("second", "cat > /dev/null"))
+            .clone();
+        let handle = Runner::new().pipeline(&pipeline).execute();
+        let results = handle.join().unwrap();
+
+        assert_eq!(pipeline.exit(&results), Some(ProcessExit::Exited(2)));
+        assert_eq!(pipeline.exit(&results[1..]), None);
+    }
+}
@@ -0,0 +1,443 @@
+use super::command_from_string;
+use super::Command;
+use super::CommandError;
+use super::Runner;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// A set of commands loaded from a [foreman](https://github.com/ddollar/foreman) or [honcho](https://github.com/nickstenning/honcho) style `Procfile`.
+///
+/// Every line of a Procfile is either blank, a comment starting with `#` or a process type of the form `name: command`.
+/// Names may only contain letters, digits, `_` and `-`. Commands are parsed with [Command::from_string], so, like there,
+/// pipes, variable expansion and other shell features need an explicit `sh -c "..."`.
+///
+/// Environment variables from `.env` files (see [Procfile::env_file]) are set on every command.
+/// By default every process type runs once under its own name. Setting an instance count (see [Procfile::concurrency])
+/// runs that many copies named `name.1`, `name.2` and so on, just like `foreman start -c name=N` does.
+/// ## Example
+/// ```
+/// use currant::{ChannelCommand, Procfile, Runner};
+///
+/// let procfile = Procfile::parse("# services\nweb: echo web\nworker: echo worker\n").unwrap();
+/// let mut runner: Runner<ChannelCommand> = procfile
+///     .clone()
+///     .env("GREETING", "hello")
+///     .concurrency_spec("worker=2")
+///     .unwrap()
+///     .runner()
+///     .unwrap();
+///
+/// let results = runner.execute().join().unwrap();
+/// let mut names: Vec<String> = results.into_iter().map(|result| result.name).collect();
+/// names.sort();
+/// assert_eq!(names, vec!["web", "worker.1", "worker.2"]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Procfile {
+    processes: Vec<(String, String)>,
+    env: Vec<(String, String)>,
+    concurrency: HashMap<String, usize>,
+    dir: Option<PathBuf>,
+}
+
+impl Procfile {
+    /// Parses the contents of a Procfile. The commands run in the current working directory of this process.
+    /// Returns [CommandError::InvalidProcfile] if a line isn't a comment or a `name: command` pair or if a name is used twice.
+    pub fn parse<S: AsRef<str>>(contents: S) -> Result<Self, CommandError> {
+        let mut processes: Vec<(String, String)> = Vec::new();
+        for (idx, line) in contents.as_ref().lines().enumerate() {
+            let line_num = idx + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (name, command) = match line.split_once(':') {
+                Some((name, command)) => (name.trim(), command.trim()),
+                None => {
+                    return Err(CommandError::InvalidProcfile(
+                        line_num,
+                        "expected 'name: command'".to_string(),
+                    ))
+                }
+            };
+            if !is_valid_name(name) {
+                return Err(CommandError::InvalidProcfile(
+                    line_num,
+                    format!("invalid process name '{}'", name),
+                ));
+            }
+            if command.is_empty() {
+                return Err(CommandError::InvalidProcfile(
+                    line_num,
+                    format!("no command for process '{}'", name),
+                ));
+            }
+            if processes.iter().any(|(existing, _)| existing == name) {
+                return Err(CommandError::InvalidProcfile(
+                    line_num,
+                    format!("duplicate process name '{}'", name),
+                ));
+            }
+            processes.push((name.to_string(), command.to_string()));
+        }
+
+        Ok(Procfile {
+            processes,
+            env: Vec::new(),
+            concurrency: HashMap::new(),
+            dir: None,
+        })
+    }
+
+    /// Reads and parses a Procfile (see [Procfile::parse]).
+    /// Like in foreman, the commands run in the directory that contains the Procfile.
+    /// Returns [CommandError::ReadError] if the file can't be read.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, CommandError> {
+        let path = path.as_ref();
+        let mut procfile = Procfile::parse(read_file(path)?)?;
+        procfile.dir = path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .map(|dir| dir.to_path_buf());
+        Ok(procfile)
+    }
+
+    /// Loads the variables of a `.env` file and sets them on every command.
+    /// Files loaded later override the variables of files loaded earlier.
+    ///
+    /// Every line is either blank, a comment starting with `#` or a `KEY=value` pair, optionally preceded by `export`.
+    /// Values may be wrapped in single quotes, which are taken literally, or double quotes, which support the `\n`, `\t`, `\"` and `\\` escapes.
+    /// Unquoted values end at the first ` #`.
+    /// Returns [CommandError::ReadError] if the file can't be read or [CommandError::InvalidEnvFile] if a line can't be parsed.
+    pub fn env_file<P: AsRef<Path>>(&mut self, path: P) -> Result<&mut Self, CommandError> {
+        for (key, val) in parse_env(&read_file(path.as_ref())?)? {
+            self.env(key, val);
+        }
+        Ok(self)
+    }
+
+    /// Sets a single environment variable on every command, overriding the variable if a `.env` file set it already
+    pub fn env<K, V>(&mut self, key: K, val: V) -> &mut Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let key = key.into();
+        self.env.retain(|(existing, _)| existing != &key);
+        self.env.push((key, val.into()));
+        self
+    }
+
+    /// Runs `count` instances of the process type `name`, named `name.1` up to `name.N`.
+    /// A count of 0 leaves the process type out entirely.
+    pub fn concurrency<S: Into<String>>(&mut self, name: S, count: usize) -> &mut Self {
+        self.concurrency.insert(name.into(), count);
+        self
+    }
+
+    /// Sets instance counts from a foreman style specification like `web=2,worker=1` (the argument of `foreman start -c`).
+    /// Returns [CommandError::InvalidConcurrency] if the specification can't be parsed.
+    pub fn concurrency_spec<S: AsRef<str>>(&mut self, spec: S) -> Result<&mut Self, CommandError> {
+        for part in spec.as_ref().split(',') {
+            let part = part.trim();
+            if part.is_empty() {
+                continue;
+            }
+            let (name, count) = part
+                .split_once('=')
+                .map(|(name, count)| (name.trim(), count.trim().parse::<usize>()))
+                .ok_or_else(|| CommandError::InvalidConcurrency(part.to_string()))?;
+            match count {
+                Ok(count) if is_valid_name(name) => {
+                    self.concurrency(name, count);
+                }
+                _ => return Err(CommandError::InvalidConcurrency(part.to_string())),
+            }
+        }
+        Ok(self)
+    }
+
+    /// The names of the process types, in the order of the Procfile
+    pub fn names(&self) -> Vec<&str> {
+        self.processes
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+
+    /// Builds the commands of every process instance, in the order of the Procfile.
+    /// Returns [CommandError::InvalidConcurrency] if an instance count was set for a process type that isn't part of the Procfile.
+    pub fn commands<C: Command>(&self) -> Result<Vec<C>, CommandError> {
+        for name in self.concurrency.keys() {
+            if !self.processes.iter().any(|(existing, _)| existing == name) {
+                return Err(CommandError::InvalidConcurrency(format!(
+                    "unknown process '{}'",
+                    name
+                )));
+            }
+        }
+
+        // the executables are looked up in the PATH the commands run with
+        let path_var = self
+            .env
+            .iter()
+            .rev()
+            .find(|(key, _)| key == "PATH")
+            .map(|(_, val)| val);
+        let mut commands = Vec::new();
+        for (name, command_string) in &self.processes {
+            let names = match self.concurrency.get(name) {
+                Some(count) => (1..=*count)
+                    .map(|num| format!("{}.{}", name, num))
+                    .collect(),
+                None => vec![name.clone()],
+            };
+            for name in names {
+                let mut cmd: C = command_from_string(
+                    name,
+                    command_string,
+                    self.dir.clone(),
+                    path_var.map(OsStr::new),
+                )?;
+                for (key, val) in &self.env {
+                    cmd.env(key, val);
+                }
+                commands.push(cmd);
+            }
+        }
+        Ok(commands)
+    }
+
+    /// Builds a [Runner] with the commands of every process instance and default options.
+    /// See [Procfile::commands] for the errors.
+    pub fn runner<C: Command>(&self) -> Result<Runner<C>, CommandError> {
+        let mut runner = Runner::new();
+        runner.commands = self.commands()?;
+        Ok(runner)
+    }
+}
+
+impl AsRef<Procfile> for Procfile {
+    fn as_ref(&self) -> &Procfile {
+        self
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn read_file(path: &Path) -> Result<String, CommandError> {
+    fs::read_to_string(path)
+        .map_err(|e| CommandError::ReadError(path.display().to_string(), e.to_string()))
+}
+
+/// Parses the contents of a `.env` file into its variables, in the order they appear in the file
+fn parse_env(contents: &str) -> Result<Vec<(String, String)>, CommandError> {
+    let mut vars = Vec::new();
+    for (idx, line) in contents.lines().enumerate() {
+        let line_num = idx + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line).trim_start();
+
+        let (key, val) = line.split_once('=').ok_or_else(|| {
+            CommandError::InvalidEnvFile(line_num, "expected 'KEY=value'".to_string())
+        })?;
+        let key = key.trim();
+        if key.is_empty()
+            || !key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        {
+            return Err(CommandError::InvalidEnvFile(
+                line_num,
+                format!("invalid variable name '{}'", key),
+            ));
+        }
+
+        let val = parse_env_value(val.trim_start())
+            .map_err(|reason| CommandError::InvalidEnvFile(line_num, reason))?;
+        vars.push((key.to_string(), val));
+    }
+    Ok(vars)
+}
+
+fn parse_env_value(val: &str) -> Result<String, String> {
+    let mut chars = val.chars();
+    let quote = match chars.next() {
+        Some(quote @ ('"' | '\'')) => quote,
+        _ => {
+            let end = val.find(" #").unwrap_or(val.len());
+            return Ok(val[..end].trim_end().to_string());
+        }
+    };
+
+    let mut parsed = String::new();
+    loop {
+        match chars.next() {
+            Some(c) if c == quote => break,
+            Some('\\') if quote == '"' => match chars.next() {
+                Some('n') => parsed.push('\n'),
+                Some('t') => parsed.push('\t'),
+                Some(c @ ('"' | '\\' | '$')) => parsed.push(c),
+                Some(c) => {
+                    parsed.push('\\');
+                    parsed.push(c);
+                }
+                None => return Err("unterminated quote".to_string()),
+            },
+            Some(c) => parsed.push(c),
+            None => return Err("unterminated quote".to_string()),
+        }
+    }
+
+    let rest = chars.as_str().trim_start();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        return Err(format!("unexpected text after quoted value: {}", rest));
+    }
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_env;
+    use super::Procfile;
+    use crate::ChannelCommand;
+    use crate::CommandError;
+    use crate::OutputMessagePayload;
+
+    #[test]
+    fn parses_procfile() {
+        let procfile = Procfile::parse(
+            "# comment\n\nweb: ruby -run -e httpd . -p 5000\n  worker-1 :echo 'a: b'\n",
+        )
+        .unwrap();
+        assert_eq!(procfile.names(), vec!["web", "worker-1"]);
+        assert_eq!(procfile.processes[1].1, "echo 'a: b'");
+
+        assert!(matches!(
+            Procfile::parse("web: a\nnot a process\n"),
+            Err(CommandError::InvalidProcfile(2, _))
+        ));
+        assert!(matches!(
+            Procfile::parse("web: a\nweb: b\n"),
+            Err(CommandError::InvalidProcfile(2, _))
+        ));
+        assert!(matches!(
+            Procfile::parse("we b: a\n"),
+            Err(CommandError::InvalidProcfile(1, _))
+        ));
+        assert!(matches!(
+            Procfile::parse("web:\n"),
+            Err(CommandError::InvalidProcfile(1, _))
+        ));
+    }
+
+    #[test]
+    fn parses_env() {
+        let vars = parse_env(
+            "# comment\nA=1\nexport B = two words # note\nC=\"quoted # \\\"x\\\"\\n\"\nD='$literal\\n' # note\nE=\n",
+        )
+        .unwrap();
+        let expected = vec![
+            ("A", "1"),
+            ("B", "two words"),
+            ("C", "quoted # \"x\"\n"),
+            ("D", "$literal\\n"),
+            ("E", ""),
+        ];
+        let expected: Vec<(String, String)> = expected
+            .into_iter()
+            .map(|(key, val)| (key.to_string(), val.to_string()))
+            .collect();
+        assert_eq!(vars, expected);
+
+        assert!(matches!(
+            parse_env("A=1\nB\n"),
+            Err(CommandError::InvalidEnvFile(2, _))
+        ));
+        assert!(matches!(
+            parse_env("A=\"open\n"),
+            Err(CommandError::InvalidEnvFile(1, _))
+        ));
+    }
+
+    #[test]
+    fn runs_instances() {
+        let dir = std::env::temp_dir().join(format!("currant-procfile-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("bin")).unwrap();
+        std::fs::write(
+            dir.join("Procfile"),
+            "greet: sh -c 'echo $GREETING from $(basename $(pwd))'\nidle: true\ntool: currant-procfile-tool\n",
+        )
+        .unwrap();
+        // the tool is only found in the PATH of the .env file
+        let tool = dir.join("bin").join("currant-procfile-tool");
+        std::fs::write(&tool, "#!/bin/sh\necho tool\n").unwrap();
+        std::fs::set_permissions(&tool, std::os::unix::fs::PermissionsExt::from_mode(0o755))
+            .unwrap();
+        std::fs::write(
+            dir.join(".env"),
+            format!(
+                "GREETING=hello\nPATH={}:{}\n",
+                dir.join("bin").display(),
+                std::env::var("PATH").unwrap()
+            ),
+        )
+        .unwrap();
+
+        let mut procfile = Procfile::from_file(dir.join("Procfile")).unwrap();
+        procfile.env_file(dir.join(".env")).unwrap();
+        let handle = procfile
+            .concurrency("greet", 2)
+            .concurrency("idle", 0)
+            .runner::<ChannelCommand>()
+            .unwrap()
+            .execute();
+
+        let mut lines = Vec::new();
+        for msg in &handle {
+            if let OutputMessagePayload::Stdout(_, bytes) = msg.message {
+                lines.push(format!(
+                    "{}: {}",
+                    msg.name,
+                    String::from_utf8(bytes).unwrap()
+                ));
+            }
+        }
+        handle.join().unwrap();
+        lines.sort();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let dir_name = dir.file_name().unwrap().to_string_lossy();
+        assert_eq!(
+            lines,
+            vec![
+                format!("greet.1: hello from {}", dir_name),
+                format!("greet.2: hello from {}", dir_name),
+                "tool: tool".to_string(),
+            ]
+        );
+
+        assert!(matches!(
+            procfile
+                .concurrency("unknown", 1)
+                .commands::<ChannelCommand>(),
+            Err(CommandError::InvalidConcurrency(_))
+        ));
+        assert!(matches!(
+            procfile.concurrency_spec("web=two"),
+            Err(CommandError::InvalidConcurrency(_))
+        ));
+    }
+}
@@ -0,0 +1,215 @@
+use super::dependency::DependencyTracker;
+use super::message::MessageSender;
+use super::CommandError;
+use super::OutputMessagePayload;
+use regex::Regex;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// How often the TCP port and file probes are checked
+const PROBE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Describes when a command counts as "ready".
+/// Set via [Command::ready_when](crate::Command::ready_when). Once the probe passes, an
+/// [OutputMessagePayload::Ready](crate::OutputMessagePayload::Ready) message is sent and commands depending on this
+/// command with [DependencyCondition::Ready](crate::DependencyCondition::Ready) are started.
+#[derive(Clone, Debug)]
+pub enum ReadinessProbe {
+    /// Ready once a line of standard out or standard error matches the regular expression.
+    /// The line is matched after lossy UTF-8 conversion. See [ReadinessProbe::log_line] for a convenience constructor.
+    LogLine(Regex),
+    /// Ready once a TCP connection to the given port on `localhost` is accepted.
+    TcpPort(u16),
+    /// Ready once the file exists. Relative paths are resolved against the command's directory.
+    File(PathBuf),
+}
+
+impl ReadinessProbe {
+    /// Construct a [ReadinessProbe::LogLine] probe from a regular expression string.
+    /// Returns [CommandError::InvalidPattern] if the regular expression can't be compiled.
+    pub fn log_line<S: AsRef<str>>(pattern: S) -> Result<Self, CommandError> {
+        Regex::new(pattern.as_ref())
+            .map(ReadinessProbe::LogLine)
+            .map_err(|e| CommandError::InvalidPattern(e.to_string()))
+    }
+}
+
+/// Announces that a single run of a command became ready. Only the first call to [ReadyNotifier::notify] has an effect.
+pub struct ReadyNotifier {
+    name: String,
+    send_chan: MessageSender,
+    tracker: DependencyTracker,
+    pattern: Option<Regex>,
+    fired: AtomicBool,
+    cancelled: AtomicBool,
+}
+
+impl ReadyNotifier {
+    pub fn new(
+        name: &str,
+        send_chan: MessageSender,
+        tracker: DependencyTracker,
+        probe: Option<&ReadinessProbe>,
+    ) -> Self {
+        let pattern = match probe {
+            Some(ReadinessProbe::LogLine(pattern)) => Some(pattern.clone()),
+            _ => None,
+        };
+
+        ReadyNotifier {
+            name: name.to_string(),
+            send_chan,
+            tracker,
+            pattern,
+            fired: AtomicBool::new(false),
+            cancelled: AtomicBool::new(false),
+        }
+    }
+
+    pub fn notify(&self) {
+        if self.fired.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        self.send_chan.send(OutputMessagePayload::Ready);
+        self.tracker.mark_ready(&self.name);
+    }
+
+    /// Returns true if a line of output passes the log line probe (if any) and the command isn't ready yet
+    pub fn matches_line(&self, line: &[u8]) -> bool {
+        match &self.pattern {
+            Some(pattern) => {
+                !self.fired.load(Ordering::SeqCst)
+                    && pattern.is_match(&String::from_utf8_lossy(line))
+            }
+            None => false,
+        }
+    }
+
+    /// Stops any polling probe. Called once the process has exited
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Starts a thread polling the TCP port or file probe until it passes or the notifier is cancelled.
+/// Log line probes are checked by the stream readers instead, so nothing is started for them.
+pub fn start_polling(probe: &ReadinessProbe, cur_dir: Option<&Path>, notifier: Arc<ReadyNotifier>) {
+    let check: Box<dyn Fn() -> bool + Send> = match probe {
+        ReadinessProbe::LogLine(_) => return,
+        ReadinessProbe::TcpPort(port) => {
+            let port = *port;
+            Box::new(move || port_accepts(port))
+        }
+        ReadinessProbe::File(path) => {
+            let path = match cur_dir {
+                Some(dir) => dir.join(path),
+                None => path.clone(),
+            };
+            Box::new(move || path.exists())
+        }
+    };
+
+    thread::spawn(move || {
+        while !notifier.cancelled.load(Ordering::SeqCst) {
+            if check() {
+                notifier.notify();
+                return;
+            }
+            thread::sleep(PROBE_INTERVAL);
+        }
+    });
+}
+
+fn port_accepts(port: u16) -> bool {
+    match ("localhost", port).to_socket_addrs() {
+        Ok(addrs) => addrs
+            .into_iter()
+            .any(|addr| TcpStream::connect_timeout(&addr, PROBE_INTERVAL).is_ok()),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ReadinessProbe;
+    use crate::ChannelCommand;
+    use crate::Command;
+    use crate::DependencyCondition;
+    use crate::OutputMessagePayload;
+    use crate::Runner;
+    use crate::CURRENT_WORKING_DIRECTORY;
+    use std::fs;
+
+    #[test]
+    fn log_line_probe() {
+        let handle = Runner::new()
+            .command(
+                ChannelCommand::from_argv(
+                    "server",
+                    "sh",
+                    ["-c", "echo starting; echo listening; sleep 0.2"],
+                    CURRENT_WORKING_DIRECTORY,
+                )
+                .unwrap()
+                .ready_when(ReadinessProbe::log_line("^listen").unwrap()),
+            )
+            .command(
+                ChannelCommand::from_string("client", "true", CURRENT_WORKING_DIRECTORY)
+                    .unwrap()
+                    .depends_on("server", DependencyCondition::Ready),
+            )
+            .execute();
+
+        let mut events = Vec::new();
+        for msg in &handle {
+            match msg.message {
+                OutputMessagePayload::Start => events.push(format!("{} start", msg.name)),
+                OutputMessagePayload::Ready => events.push(format!("{} ready", msg.name)),
+                OutputMessagePayload::Stdout(_, bytes) => {
+                    events.push(String::from_utf8_lossy(&bytes).to_string())
+                }
+                _ => {}
+            }
+        }
+        handle.join().unwrap();
+
+        assert_eq!(
+            &events[..4],
+            &["server start", "starting", "listening", "server ready"]
+        );
+        assert_eq!(events[4], "client start");
+    }
+
+    #[test]
+    fn file_probe() {
+        let marker = std::env::temp_dir().join(format!("currant_ready_{}", std::process::id()));
+        let _ = fs::remove_file(&marker);
+        let script = format!("sleep 0.2; touch {}; sleep 0.2", marker.display());
+
+        let handle = Runner::new()
+            .command(
+                ChannelCommand::from_argv(
+                    "worker",
+                    "sh",
+                    ["-c", &script],
+                    CURRENT_WORKING_DIRECTORY,
+                )
+                .unwrap()
+                .ready_when(ReadinessProbe::File(marker.clone())),
+            )
+            .execute();
+
+        handle.wait_until_ready("worker").unwrap();
+        assert!(marker.exists());
+        assert!(handle.wait_until_ready("bogus").is_err());
+        handle.join().unwrap();
+        let _ = fs::remove_file(&marker);
+    }
+}
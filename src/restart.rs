@@ -0,0 +1,322 @@
+use std::time::Duration;
+
+/// Which exits cause a command to be restarted under a [RestartPolicy]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RestartOn {
+    /// (DEFAULT) Only restart when the command fails: a non-zero exit status, being killed by a signal or failing to spawn
+    Failure,
+    /// Restart whenever the command exits, even when it succeeds
+    Always,
+}
+
+/// Fine-grained control over how a command is restarted.
+/// Used by [RestartOptions::RestartWith](crate::RestartOptions::RestartWith). [RestartOptions::Restart](crate::RestartOptions::Restart)
+/// uses the default policy.
+///
+/// The delay before restart number `n` is `initial_delay * multiplier^(n - 1)`, capped at `max_delay`, and then
+/// randomly moved up or down by up to `jitter` (as a fraction of the delay), without going over `max_delay`, so that failing commands
+/// don't restart in lockstep. `max_delay` can be set to `Duration::MAX` for no cap.
+/// ## Example
+/// ```
+/// use currant::{RestartOn, RestartOptions, RestartPolicy};
+/// use std::time::Duration;
+///
+/// let restart = RestartOptions::RestartWith(RestartPolicy {
+///     restart_on: RestartOn::Always,
+///     max_attempts: Some(5),
+///     initial_delay: Duration::from_secs(1),
+///     ..RestartPolicy::default()
+/// });
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct RestartPolicy {
+    /// Which exits trigger a restart. Defaults to [RestartOn::Failure].
+    pub restart_on: RestartOn,
+    /// The maximum number of consecutive restarts. `None` (the default) restarts forever.
+    pub max_attempts: Option<u32>,
+    /// The delay before the first restart. Defaults to 100 milliseconds.
+    pub initial_delay: Duration,
+    /// The upper bound of the delay between restarts. Defaults to 30 seconds.
+    pub max_delay: Duration,
+    /// The factor the delay grows by after every restart. Defaults to 2. Values below 1 (and NaN) are treated as 1.
+    pub multiplier: f64,
+    /// The fraction (between 0 and 1) of the delay that is randomly added or subtracted. Defaults to 0.1. NaN is treated as 0.
+    pub jitter: f64,
+    /// Once a run of the command stays up for this long, the attempt counter and the delay start over.
+    /// Defaults to 10 seconds. `None` never resets them.
+    pub reset_after: Option<Duration>,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy {
+            restart_on: RestartOn::Failure,
+            max_attempts: None,
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.1,
+            reset_after: Some(Duration::from_secs(10)),
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// Returns the delay before restart number `attempt` (starting at 1), without jitter
+    fn base_delay(&self, attempt: u32) -> Duration {
+        let factor = self
+            .multiplier
+            .max(1.0)
+            .powi(attempt.saturating_sub(1) as i32);
+        if self.initial_delay.is_zero() {
+            return Duration::ZERO;
+        }
+        let delay = self.initial_delay.as_secs_f64() * factor;
+        // too long for a Duration (or infinite) means it's over any max_delay, even Duration::MAX
+        Duration::try_from_secs_f64(delay).map_or(self.max_delay, |delay| delay.min(self.max_delay))
+    }
+}
+
+/// Keeps track of the restarts of a single command
+pub struct RestartTracker {
+    policy: RestartPolicy,
+    attempt: u32,
+}
+
+impl RestartTracker {
+    pub fn new(policy: RestartPolicy) -> Self {
+        RestartTracker { policy, attempt: 0 }
+    }
+
+    /// Decides whether to restart after a run that ended with `success` and lasted `uptime` (`None` if the command didn't spawn).
+    /// Returns the attempt number and the delay to wait before restarting, or `None` if the command shouldn't be restarted.
+    pub fn next_restart(
+        &mut self,
+        success: bool,
+        uptime: Option<Duration>,
+    ) -> Option<(u32, Duration)> {
+        if success && self.policy.restart_on == RestartOn::Failure {
+            return None;
+        }
+
+        if let (Some(reset_after), Some(uptime)) = (self.policy.reset_after, uptime) {
+            if uptime >= reset_after {
+                self.attempt = 0;
+            }
+        }
+
+        if let Some(max_attempts) = self.policy.max_attempts {
+            if self.attempt >= max_attempts {
+                return None;
+            }
+        }
+
+        self.attempt += 1;
+        let delay = self.policy.base_delay(self.attempt);
+        // clamp keeps NaN, which would make mul_f64 panic
+        let jitter = match self.policy.jitter {
+            jitter if jitter.is_nan() => 0.0,
+            jitter => jitter.clamp(0.0, 1.0),
+        };
+        let spread = (rand::random::<f64>() * 2.0 - 1.0) * jitter;
+        let offset = delay.mul_f64(spread.abs());
+        let delay = if spread < 0.0 {
+            delay.saturating_sub(offset)
+        } else {
+            delay.saturating_add(offset).min(self.policy.max_delay)
+        };
+        Some((self.attempt, delay))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RestartOn;
+    use super::RestartPolicy;
+    use super::RestartTracker;
+    use crate::ChannelCommand;
+    use crate::Command;
+    use crate::OutputMessagePayload;
+    use crate::RestartOptions;
+    use crate::Runner;
+    use crate::CURRENT_WORKING_DIRECTORY;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    fn policy() -> RestartPolicy {
+        RestartPolicy {
+            max_attempts: Some(3),
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(300),
+            jitter: 0.0,
+            reset_after: Some(Duration::from_secs(1)),
+            ..RestartPolicy::default()
+        }
+    }
+
+    #[test]
+    fn exponential_backoff_with_limit() {
+        let mut tracker = RestartTracker::new(policy());
+        let delays: Vec<_> = (0..4).map(|_| tracker.next_restart(false, None)).collect();
+        assert_eq!(
+            delays,
+            vec![
+                Some((1, Duration::from_millis(100))),
+                Some((2, Duration::from_millis(200))),
+                Some((3, Duration::from_millis(300))),
+                None,
+            ]
+        );
+
+        let mut tracker = RestartTracker::new(RestartPolicy {
+            multiplier: f64::NAN,
+            jitter: f64::NAN,
+            ..policy()
+        });
+        tracker.next_restart(false, None);
+        assert_eq!(
+            tracker.next_restart(false, None),
+            Some((2, Duration::from_millis(100)))
+        );
+    }
+
+    #[test]
+    fn uncapped_delay_saturates() {
+        let mut tracker = RestartTracker::new(RestartPolicy {
+            max_attempts: None,
+            max_delay: Duration::MAX,
+            jitter: 0.5,
+            ..policy()
+        });
+        for _ in 0..1100 {
+            let (_, delay) = tracker.next_restart(false, None).unwrap();
+            assert!(delay >= Duration::from_millis(50));
+        }
+
+        let mut tracker = RestartTracker::new(RestartPolicy {
+            max_attempts: None,
+            max_delay: Duration::MAX,
+            jitter: 0.0,
+            ..policy()
+        });
+        let delays: Vec<_> = (0..1100)
+            .map(|_| tracker.next_restart(false, None).unwrap().1)
+            .collect();
+        assert_eq!(delays[1099], Duration::MAX);
+    }
+
+    #[test]
+    fn resets_after_stable_uptime() {
+        let mut tracker = RestartTracker::new(policy());
+        tracker.next_restart(false, Some(Duration::ZERO));
+        tracker.next_restart(false, Some(Duration::ZERO));
+        assert_eq!(
+            tracker.next_restart(false, Some(Duration::from_secs(2))),
+            Some((1, Duration::from_millis(100)))
+        );
+    }
+
+    #[test]
+    fn restart_on() {
+        let mut tracker = RestartTracker::new(policy());
+        assert_eq!(tracker.next_restart(true, None), None);
+
+        let mut tracker = RestartTracker::new(RestartPolicy {
+            restart_on: RestartOn::Always,
+            ..policy()
+        });
+        assert!(tracker.next_restart(true, None).is_some());
+    }
+
+    #[test]
+    fn restarts_until_limit() {
+        let handle = Runner::new()
+            .command(
+                ChannelCommand::from_string("fail", "false", CURRENT_WORKING_DIRECTORY).unwrap(),
+            )
+            .restart(RestartOptions::RestartWith(RestartPolicy {
+                max_attempts: Some(2),
+                initial_delay: Duration::from_millis(10),
+                ..policy()
+            }))
+            .execute();
+
+        let mut events = Vec::new();
+        for msg in &handle {
+            match msg.message {
+                OutputMessagePayload::Start => events.push("start".to_string()),
+                OutputMessagePayload::Done(_) => events.push("done".to_string()),
+                OutputMessagePayload::Restarting { attempt, delay, .. } => {
+                    events.push(format!("restart {} {:?}", attempt, delay))
+                }
+                _ => {}
+            }
+        }
+        let results = handle.join().unwrap();
+
+        assert_eq!(
+            events,
+            vec![
+                "start",
+                "done",
+                "restart 1 10ms",
+                "start",
+                "done",
+                "restart 2 20ms",
+                "start",
+                "done"
+            ]
+        );
+        assert!(!results[0].status.unwrap().success());
+    }
+
+    #[test]
+    fn command_overrides_runner_restart() {
+        let handle = Runner::new()
+            .command(
+                ChannelCommand::from_string("build", "false", CURRENT_WORKING_DIRECTORY)
+                    .unwrap()
+                    .restart(RestartOptions::Kill),
+            )
+            .command(
+                ChannelCommand::from_string("server", "sleep 5", CURRENT_WORKING_DIRECTORY)
+                    .unwrap(),
+            )
+            .restart(RestartOptions::Restart)
+            .execute();
+
+        let mut restarts = 0;
+        for msg in &handle {
+            if let OutputMessagePayload::Restarting { .. } = msg.message {
+                restarts += 1;
+            }
+        }
+        let results = handle.join().unwrap();
+
+        assert_eq!(restarts, 0);
+        assert!(!results[0].status.unwrap().success());
+        assert!(!results[1].status.unwrap().success());
+    }
+
+    #[test]
+    fn kills_on_exit() {
+        let started = Instant::now();
+        let results = Runner::new()
+            .command(
+                ChannelCommand::from_string("server", "sleep 5", CURRENT_WORKING_DIRECTORY)
+                    .unwrap(),
+            )
+            .command(
+                ChannelCommand::from_string("tests", "true", CURRENT_WORKING_DIRECTORY)
+                    .unwrap()
+                    .restart(RestartOptions::KillOnExit),
+            )
+            .execute()
+            .join()
+            .unwrap();
+
+        assert!(started.elapsed() < Duration::from_secs(4));
+        assert!(!results[0].status.unwrap().success());
+        assert!(results[1].status.unwrap().success());
+    }
+}
@@ -1,5 +1,15 @@
+use super::child;
+use super::control;
+use super::dependency;
 use super::kill_barrier;
 use super::line_parse;
+use super::message;
+use super::pipeline;
+use super::readiness;
+use super::restart;
+use super::slots;
+use super::watch;
+use super::CommandError;
 use super::CommandHandle;
 use super::ExitResult;
 use super::InnerCommand;
@@ -7,13 +17,23 @@ use super::Options;
 use super::OutputMessage;
 use super::OutputMessagePayload;
 use super::RestartOptions;
+use super::StdinMode;
+use std::io;
 use std::io::BufRead;
 use std::io::BufReader;
+use std::io::Write;
+use std::os::unix::process::CommandExt;
 use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+/// How often a kill thread checks whether its process has already exited
+const KILL_THREAD_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
 pub(super) fn run_commands_internal(
     commands: Vec<InnerCommand>,
@@ -21,111 +41,548 @@ pub(super) fn run_commands_internal(
 ) -> CommandHandle {
     let (send, recv) = mpsc::channel();
     let kill_trigger = kill_barrier::KillBarrier::new();
-    let kill_trigger_clone = kill_trigger.clone();
-    let mut pid_list = Vec::new();
+    let tracker = dependency::DependencyTracker::new();
+    let pids: control::PidTable = Arc::new(Mutex::new(Vec::new()));
+
+    let subreaper_error = if options.child_subreaper {
+        become_child_subreaper()
+            .err()
+            .map(|e| format!("couldn't become a child subreaper: {}", e))
+    } else {
+        None
+    };
+
+    let exit_policy = options.exit_policy.clone();
+    let command_set = CommandSet::new(
+        Spawner {
+            send,
+            started: Instant::now(),
+            slots: slots::Slots::new(options.max_parallel),
+            options,
+            kill_trigger: kill_trigger.clone(),
+            tracker: tracker.clone(),
+            pids: pids.clone(),
+            subreaper_error,
+        },
+        &commands,
+    );
+
+    let command_set_clone = command_set.clone();
+    let handle = thread::spawn(move || {
+        command_set_clone.start(commands);
+        command_set_clone.join_all()
+    });
+
+    CommandHandle {
+        handle: Supervisor::new(handle, kill_trigger.clone()),
+        channel: recv,
+        kill_trigger,
+        pids,
+        tracker,
+        commands: command_set,
+        exit_policy,
+    }
+}
+
+/// Everything needed to start another command of a set
+struct Spawner {
+    send: mpsc::Sender<OutputMessage>,
+    started: Instant,
+    options: Options,
+    kill_trigger: kill_barrier::KillBarrier,
+    tracker: dependency::DependencyTracker,
+    slots: slots::Slots,
+    pids: control::PidTable,
+    /// Why [Runner::child_subreaper](crate::Runner::child_subreaper) couldn't be applied, reported by every command
+    subreaper_error: Option<String>,
+}
 
-    for cmd in commands.iter() {
-        pid_list.push(Arc::new((cmd.name.clone(), Mutex::new(None))));
+impl Spawner {
+    fn spawn(
+        &self,
+        command: InnerCommand,
+        stage: pipeline::StageIo,
+        index: usize,
+        entry: &control::PidEntry,
+    ) -> thread::JoinHandle<ExitResult> {
+        let ticket = self.slots.ticket(command.priority, index, command.pipeline);
+        if command.depends_on.is_empty() {
+            ticket.enqueue();
+        }
+        let sender = message::MessageSender::new(&command.name, self.send.clone(), self.started);
+        if let Some(e) = &self.subreaper_error {
+            // the command still runs, only its orphaned descendants end up with init
+            sender.send(OutputMessagePayload::Error(io::Error::other(e.clone())));
+        }
+        run_command(
+            command,
+            stage,
+            sender,
+            self.options.clone(),
+            self.tracker.clone(),
+            ticket,
+            entry,
+        )
     }
+}
 
-    let pid_list_clone = pid_list.clone();
+struct CommandSetState {
+    /// Dropped once every command is done for good, which closes the output channel
+    spawner: Option<Spawner>,
+    /// The names and threads of all commands in the order they were added.
+    /// The thread is missing if the command was never started or the supervisor is already joining it.
+    threads: Vec<(String, Option<thread::JoinHandle<ExitResult>>)>,
+}
 
-    let command_names: Vec<String> = commands.iter().map(|cmd| cmd.name.clone()).collect();
+/// The commands of a handle. Commands can be added and removed until all of them are done for good
+#[derive(Clone)]
+pub(super) struct CommandSet {
+    state: Arc<Mutex<CommandSetState>>,
+}
 
-    let handle = thread::spawn(move || {
-        let mut handles = Vec::new();
+impl CommandSet {
+    fn new(spawner: Spawner, commands: &[InnerCommand]) -> Self {
+        if let Ok(mut pids) = spawner.pids.lock() {
+            for cmd in commands {
+                spawner.tracker.register(&cmd.name);
+                pids.push(control::CommandEntry::new(
+                    &cmd.name,
+                    spawner.kill_trigger.child(),
+                    cmd.stdin == StdinMode::Interactive,
+                ));
+            }
+        }
+
+        CommandSet {
+            state: Arc::new(Mutex::new(CommandSetState {
+                spawner: Some(spawner),
+                threads: commands
+                    .iter()
+                    .map(|cmd| (cmd.name.clone(), None))
+                    .collect(),
+            })),
+        }
+    }
+
+    /// Starts the initial commands after their dependencies have been validated.
+    /// If they are invalid, no command is started and each command reports the problem as an error.
+    fn start(&self, commands: Vec<InnerCommand>) {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return,
+        };
+        let state = &mut *state;
+        let spawner = match &state.spawner {
+            Some(spawner) => spawner,
+            None => return,
+        };
+        let entries = match spawner.pids.lock() {
+            Ok(pids) => pids.clone(),
+            Err(_) => return,
+        };
+
+        let connected = dependency::validate(&commands).and_then(|order| {
+            pipeline::connect(&commands)
+                .map(|stages| (order, stages))
+                .map_err(|e| CommandError::PipeError(e.to_string()))
+        });
+        let (start_order, stages) = match connected {
+            Ok(connected) => connected,
+            Err(e) => {
+                for cmd in commands.iter() {
+                    message::MessageSender::new(&cmd.name, spawner.send.clone(), spawner.started)
+                        .send(OutputMessagePayload::Error(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            e.to_string(),
+                        )));
+                    spawner.tracker.mark_exited(&cmd.name, false);
+                }
+                return;
+            }
+        };
+
+        let mut commands: Vec<Option<(InnerCommand, pipeline::StageIo)>> =
+            commands.into_iter().zip(stages).map(Some).collect();
+        for idx in start_order {
+            if let Some((cmd, stage)) = commands[idx].take() {
+                state.threads[idx].1 = Some(spawner.spawn(cmd, stage, idx, &entries[idx]));
+            }
+        }
+    }
+
+    /// Waits for every command, including the ones added while waiting, and returns their exit results in the order they were added
+    fn join_all(&self) -> Vec<ExitResult> {
         let mut statuses = Vec::new();
-        for (idx, cmd) in commands.into_iter().enumerate() {
-            handles.push(run_command(
-                cmd,
-                send.clone(),
-                options.clone(),
-                kill_trigger_clone.clone(),
-                &pid_list_clone[idx],
+        loop {
+            let (name, handle, started) = match self.state.lock() {
+                Ok(mut state) => {
+                    let started = state.spawner.as_ref().map(|spawner| spawner.started);
+                    match state.threads.get_mut(statuses.len()) {
+                        Some((name, handle)) => (name.clone(), handle.take(), started),
+                        None => {
+                            state.spawner = None;
+                            return statuses;
+                        }
+                    }
+                }
+                Err(_) => return statuses,
+            };
+
+            let status = handle.and_then(|handle| handle.join().ok());
+            statuses.push(status.unwrap_or_else(|| {
+                let mut result = ExitResult::new(name, None, false, false);
+                result.elapsed = started.map(|started| started.elapsed()).unwrap_or_default();
+                result
+            }));
+        }
+    }
+
+    /// Starts another command. Its name may only be taken by commands that are done for good and it may only depend on
+    /// commands that are part of the set.
+    /// `on_added` is called right before the command is started.
+    pub fn add<F: FnOnce()>(&self, command: InnerCommand, on_added: F) -> Result<(), String> {
+        let mut state = self.state.lock().map_err(|e| e.to_string())?;
+        let state = &mut *state;
+        let spawner = match &state.spawner {
+            Some(spawner) if !spawner.kill_trigger.is_killed() => spawner,
+            Some(_) => return Err("commands have been killed".to_string()),
+            None => return Err("all commands have already finished".to_string()),
+        };
+        let mut pids = spawner.pids.lock().map_err(|e| e.to_string())?;
+
+        for (dep_name, _) in &command.depends_on {
+            if !pids.iter().any(|entry| &entry.name == dep_name) {
+                return Err(CommandError::UnknownDependency(
+                    command.name.clone(),
+                    dep_name.clone(),
+                )
+                .to_string());
+            }
+        }
+        if !spawner.tracker.register(&command.name) {
+            return Err(format!(
+                "a command named '{}' is already running",
+                command.name
             ));
         }
 
-        for (idx, handle) in handles.into_iter().enumerate() {
-            statuses.push(handle.join().unwrap_or((command_names[idx].clone(), None)));
+        on_added();
+        let entry = control::CommandEntry::new(
+            &command.name,
+            spawner.kill_trigger.child(),
+            command.stdin == StdinMode::Interactive,
+        );
+        pids.retain(|other| other.name != command.name);
+        pids.push(entry.clone());
+        let index = state.threads.len();
+        let name = command.name.clone();
+        let handle = spawner.spawn(command, pipeline::StageIo::default(), index, &entry);
+        state.threads.push((name, Some(handle)));
+        Ok(())
+    }
+
+    /// Stops the command named `name` with its [StopSequence](crate::StopSequence) without restarting it
+    /// and removes it from the set, so that its name is free for a new command
+    pub fn remove(&self, name: &str) -> Result<(), String> {
+        let state = self.state.lock().map_err(|e| e.to_string())?;
+        let spawner = match &state.spawner {
+            Some(spawner) => spawner,
+            None => return Err("all commands have already finished".to_string()),
+        };
+        let mut pids = spawner.pids.lock().map_err(|e| e.to_string())?;
+        let position = pids
+            .iter()
+            .position(|entry| entry.name == name)
+            .ok_or_else(|| format!("process named: '{}' not found", name))?;
+        let entry = pids.remove(position);
+        entry.kill_trigger.initiate_kill()
+    }
+}
+
+/// Owns the supervisor thread of a set of commands.
+/// Unless it has been joined or detached, dropping it kills all commands and waits for them to exit.
+pub(super) struct Supervisor {
+    thread: Option<thread::JoinHandle<Vec<ExitResult>>>,
+    kill_trigger: kill_barrier::KillBarrier,
+}
+
+impl Supervisor {
+    fn new(
+        thread: thread::JoinHandle<Vec<ExitResult>>,
+        kill_trigger: kill_barrier::KillBarrier,
+    ) -> Self {
+        Supervisor {
+            thread: Some(thread),
+            kill_trigger,
         }
+    }
 
-        statuses
-    });
+    /// Waits for all commands to exit and returns their exit results
+    pub fn join(mut self) -> thread::Result<Vec<ExitResult>> {
+        match self.thread.take() {
+            Some(thread) => thread.join(),
+            None => Ok(Vec::new()),
+        }
+    }
 
-    CommandHandle {
-        handle,
-        channel: recv,
-        kill_trigger,
-        pids: pid_list,
+    /// Lets the commands keep running once the supervisor is dropped
+    pub fn detach(mut self) {
+        self.thread.take();
+    }
+}
+
+impl Drop for Supervisor {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            let _ = self.kill_trigger.initiate_kill();
+            let _ = thread.join();
+        }
     }
 }
 
 fn run_command(
     command: InnerCommand,
-    send_chan: mpsc::Sender<OutputMessage>,
+    mut stage: pipeline::StageIo,
+    send_chan: message::MessageSender,
     options: Options,
-    kill_trigger: kill_barrier::KillBarrier,
-    pid_lock: &Arc<(String, Mutex<Option<u32>>)>,
+    tracker: dependency::DependencyTracker,
+    ticket: slots::Ticket,
+    pid_lock: &control::PidEntry,
 ) -> thread::JoinHandle<ExitResult> {
-    let command_name = command.name.clone();
-    let mut command_process: process::Command = command.into();
     let pid_lock = pid_lock.clone();
 
-    thread::spawn(move || loop {
-        let current_pid = pid_lock.1.lock();
-        let _ = send_chan.send(OutputMessage {
-            name: command_name.clone(),
-            message: OutputMessagePayload::Start,
-        });
+    thread::spawn(move || {
+        let mut result = wait_and_run(
+            command, &mut stage, &send_chan, &options, &tracker, ticket, &pid_lock,
+        );
+        result.elapsed = send_chan.elapsed();
 
-        let cmd_handle = command_process.spawn();
+        if options.kill_when_decided && options.exit_policy.decides(&result) {
+            let _ = pid_lock.kill_trigger.initiate_kill_all();
+        }
+        result
+    })
+}
+
+/// Waits for the dependencies and a slot of `command`, runs it until it is done for good and records the exit in the `tracker`
+fn wait_and_run(
+    command: InnerCommand,
+    stage: &mut pipeline::StageIo,
+    send_chan: &message::MessageSender,
+    options: &Options,
+    tracker: &dependency::DependencyTracker,
+    ticket: slots::Ticket,
+    pid_lock: &control::PidEntry,
+) -> ExitResult {
+    let command_name = command.name.clone();
+    let kill_trigger = pid_lock.kill_trigger.clone();
+    match tracker.wait_for(&command.depends_on, &kill_trigger) {
+        Ok(true) => {}
+        Ok(false) => {
+            tracker.mark_exited(&command_name, false);
+            return ExitResult::new(command_name, None, false, true);
+        }
+        Err(e) => {
+            send_chan.send(OutputMessagePayload::Error(io::Error::other(e)));
+            tracker.mark_exited(&command_name, false);
+            return ExitResult::new(command_name, None, false, false);
+        }
+    }
+
+    // held while the command runs
+    let mut slot = match ticket.acquire(&kill_trigger, || {
+        send_chan.send(OutputMessagePayload::Queued)
+    }) {
+        Ok(Some(slot)) => slot,
+        Ok(None) => {
+            tracker.mark_exited(&command_name, false);
+            return ExitResult::new(command_name, None, false, true);
+        }
+        Err(e) => {
+            send_chan.send(OutputMessagePayload::Error(io::Error::other(e)));
+            tracker.mark_exited(&command_name, false);
+            return ExitResult::new(command_name, None, false, false);
+        }
+    };
+
+    // the watcher lives across runs so that changes made while the command is restarting (or waiting) aren't missed
+    let watcher = start_watcher(&command, send_chan, options).map(Arc::new);
+    loop {
+        let result = command_loop(
+            &command,
+            stage,
+            send_chan,
+            options,
+            tracker,
+            pid_lock,
+            watcher.clone(),
+        );
+        // a run that hit its timeout failed, even if the process exited cleanly once it was stopped
+        let success = result
+            .status
+            .map(|status| status.success())
+            .unwrap_or(false)
+            && !result.timed_out;
+        if options.watch_after_exit && watcher.is_some() && !result.killed {
+            tracker.mark_waiting(&command_name, success);
+            // the slot is only needed again once the command is spawned again
+            drop(slot);
+            if reload_on_change(watcher.as_deref(), send_chan, &kill_trigger) {
+                if let Ok(Some(next_slot)) = ticket.acquire(&kill_trigger, || {
+                    send_chan.send(OutputMessagePayload::Queued)
+                }) {
+                    slot = next_slot;
+                    continue;
+                }
+            }
+        }
+        tracker.mark_exited(&command_name, success);
+        return result;
+    }
+}
+
+/// Runs `command` until it is done, restarting it according to its restart options and reloading it while it runs and
+/// the files of the `watcher` change
+fn command_loop(
+    command: &InnerCommand,
+    stage: &mut pipeline::StageIo,
+    send_chan: &message::MessageSender,
+    options: &Options,
+    tracker: &dependency::DependencyTracker,
+    pid_lock: &control::PidEntry,
+    watcher: Option<Arc<watch::Watcher>>,
+) -> ExitResult {
+    let command_name = command.name.clone();
+    let kill_trigger = &pid_lock.kill_trigger;
+    let mut restart_option = command
+        .restart
+        .clone()
+        .unwrap_or_else(|| options.restart.clone());
+    // the pipes to the other stages of a pipeline only exist for a single run
+    if command.pipeline.is_some()
+        && !matches!(
+            restart_option,
+            RestartOptions::Kill | RestartOptions::KillOnExit
+        )
+    {
+        restart_option = RestartOptions::Continue;
+    }
+    let mut restarts = restart_option
+        .restart_policy()
+        .map(restart::RestartTracker::new);
+    loop {
+        let cmd_handle = spawn_process(command, stage, options);
+        send_chan.set_pid(cmd_handle.as_ref().ok().map(|(child, _)| child.id()));
+        send_chan.send(OutputMessagePayload::Start);
         if cmd_handle.is_err() {
-            let _ = send_chan.send(OutputMessage {
-                name: command_name.clone(),
-                message: OutputMessagePayload::Error(cmd_handle.err().unwrap()),
-            });
+            send_chan.send(OutputMessagePayload::Error(cmd_handle.err().unwrap()));
+
+            // checked before a failure can throw the kill switch itself
+            let killed = kill_trigger.is_killed();
+            if should_restart(
+                &mut restarts,
+                false,
+                None,
+                send_chan,
+                &restart_option,
+                kill_trigger,
+            ) {
+                continue;
+            }
+            return ExitResult::new(command_name, None, false, killed);
+        }
 
-            match options.restart {
-                RestartOptions::Restart => continue,
-                RestartOptions::Kill => {
-                    let _ = kill_trigger.initiate_kill();
-                    return (command_name, None);
+        let (mut cmd_handle, merged_output) = cmd_handle.unwrap();
+        match (&command.stdin, cmd_handle.stdin.take()) {
+            (StdinMode::Bytes(bytes), Some(mut stdin)) => {
+                let bytes = bytes.clone();
+                // closing the pipe once all bytes are written lets the child see end of file
+                thread::spawn(move || {
+                    let _ = stdin.write_all(&bytes);
+                });
+            }
+            (StdinMode::Interactive, Some(stdin)) => {
+                if let Ok(mut current_stdin) = pid_lock.stdin.lock() {
+                    *current_stdin = Some(Arc::new(stdin));
                 }
-                RestartOptions::Continue => return (command_name, None),
             }
+            _ => {}
         }
+        tracker.mark_started(&command_name);
 
-        let mut cmd_handle = cmd_handle.unwrap();
-        if let Ok(mut current_pid) = current_pid {
-            *current_pid = Some(cmd_handle.id());
+        let notifier = Arc::new(readiness::ReadyNotifier::new(
+            &command_name,
+            send_chan.clone(),
+            tracker.clone(),
+            command.readiness.as_ref(),
+        ));
+        match &command.readiness {
+            Some(probe) => {
+                readiness::start_polling(probe, command.cur_dir.as_deref(), notifier.clone())
+            }
+            None => tracker.mark_ready(&command_name),
         }
 
         let std_out = cmd_handle.stdout.take();
         let std_err = cmd_handle.stderr.take();
         let mut std_out_handle = None;
         let mut std_err_handle = None;
+        let mut merged_handle = None;
 
-        let shared_handle = Arc::new(Mutex::new(cmd_handle));
+        let run_started = Instant::now();
+        let shared_handle = Arc::new(child::SharedChild::new(cmd_handle, options.process_groups));
+        if let Ok(mut current_child) = pid_lock.child.lock() {
+            *current_child = Some(shared_handle.clone());
+        }
 
         let child_clone = shared_handle.clone();
         let kill_trigger_clone = kill_trigger.clone();
-        thread::spawn(move || kill_thread(&kill_trigger_clone, child_clone));
+        let stop_sequence = command
+            .stop_sequence
+            .clone()
+            .unwrap_or_else(|| options.stop_sequence.clone());
+        let deadline = command.timeout.map(|timeout| run_started + timeout);
+        let timed_out = Arc::new(AtomicBool::new(false));
+        let timed_out_clone = timed_out.clone();
+        let timeout_chan = send_chan.clone();
+        let watcher_clone = watcher.clone();
+        thread::spawn(move || {
+            if kill_thread(
+                &kill_trigger_clone,
+                &child_clone,
+                deadline,
+                watcher_clone.as_deref(),
+            ) {
+                timed_out_clone.store(true, Ordering::SeqCst);
+                timeout_chan.send(OutputMessagePayload::TimedOut);
+            }
+            child_clone.stop(&stop_sequence);
+        });
 
         if let Some(output) = std_out {
             let mut buffered_stdout = BufReader::new(output);
-            let new_name = command_name.clone();
             let new_chan = send_chan.clone();
+            let new_notifier = notifier.clone();
             std_out_handle = Some(thread::spawn(move || {
-                read_stream(&new_name, new_chan, &mut buffered_stdout, true);
+                read_stream(new_chan, &mut buffered_stdout, true, &new_notifier);
             }));
         }
 
         if let Some(output) = std_err {
             let mut buffered_stdout = BufReader::new(output);
-            let new_name = command_name.clone();
             let new_chan = send_chan.clone();
+            let new_notifier = notifier.clone();
             std_err_handle = Some(thread::spawn(move || {
-                read_stream(&new_name, new_chan, &mut buffered_stdout, false);
+                read_stream(new_chan, &mut buffered_stdout, false, &new_notifier);
+            }));
+        }
+
+        if let Some(output) = merged_output {
+            let mut buffered_output = BufReader::new(output);
+            let new_chan = send_chan.clone();
+            let new_notifier = notifier.clone();
+            merged_handle = Some(thread::spawn(move || {
+                read_stream(new_chan, &mut buffered_output, true, &new_notifier);
             }));
         }
 
@@ -133,60 +590,280 @@ fn run_command(
             let _ = handle.join();
         }
 
+        if let Some(handle) = merged_handle {
+            let _ = handle.join();
+        }
+
         if let Some(handle) = std_err_handle {
             let _ = handle.join();
         }
 
-        let exit_status = shared_handle.lock().unwrap().wait();
+        let exit_status = shared_handle.wait();
+        notifier.cancel();
+        if let Ok(mut current_stdin) = pid_lock.stdin.lock() {
+            current_stdin.take();
+        }
         match exit_status {
             Ok(status) => {
-                let _ = send_chan.send(OutputMessage {
-                    name: command_name.clone(),
-                    message: OutputMessagePayload::Done(status.code()),
-                });
+                if let Some(step) = shared_handle.stop_step() {
+                    send_chan.send(OutputMessagePayload::Stopped(step));
+                }
+                send_chan.send(OutputMessagePayload::Done(status.into()));
+                // checked before a failure can throw the kill switch itself
+                let killed = kill_trigger.is_killed();
 
-                match options.restart {
-                    RestartOptions::Continue => {
-                        return (command_name, Some(status));
-                    }
-                    RestartOptions::Restart => {
-                        if status.success() {
-                            return (command_name, Some(status));
-                        }
-                    }
-                    RestartOptions::Kill => {
-                        if !status.success() {
-                            let _ = kill_trigger.initiate_kill();
-                        }
-                        return (command_name, Some(status));
-                    }
-                };
+                // a run that was stopped because files changed is neither a failure nor an exit
+                let stopped_for_changes = watcher
+                    .as_ref()
+                    .map(|watcher| watcher.has_changes())
+                    .unwrap_or(false);
+                if stopped_for_changes
+                    && reload_on_change(watcher.as_deref(), send_chan, kill_trigger)
+                {
+                    restarts = restart_option
+                        .restart_policy()
+                        .map(restart::RestartTracker::new);
+                    continue;
+                }
+                if !stopped_for_changes
+                    && should_restart(
+                        &mut restarts,
+                        status.success() && !timed_out.load(Ordering::SeqCst),
+                        Some(run_started.elapsed()),
+                        send_chan,
+                        &restart_option,
+                        kill_trigger,
+                    )
+                {
+                    continue;
+                }
+                return ExitResult::new(
+                    command_name,
+                    Some(status),
+                    timed_out.load(Ordering::SeqCst),
+                    killed,
+                );
             }
             Err(e) => {
-                let _ = send_chan.send(OutputMessage {
-                    name: command_name.clone(),
-                    message: OutputMessagePayload::Error(e),
-                });
-                return (command_name, None);
+                send_chan.send(OutputMessagePayload::Error(e));
+                return ExitResult::new(command_name, None, false, kill_trigger.is_killed());
             }
         }
-    })
+    }
+}
+
+/// Decides what happens after a run of a command ended with `success` after `uptime` (`None` if it failed to spawn).
+/// Returns true if the command should be spawned again. In that case the restart has already been announced
+/// on the channel and the restart delay has passed.
+/// Otherwise the command is done for good and, under [RestartOptions::Kill], a failure triggers the kill switch
+/// (under [RestartOptions::KillOnExit], any exit does).
+fn should_restart(
+    restarts: &mut Option<restart::RestartTracker>,
+    success: bool,
+    uptime: Option<Duration>,
+    send_chan: &message::MessageSender,
+    restart_option: &RestartOptions,
+    kill_trigger: &kill_barrier::KillBarrier,
+) -> bool {
+    if let RestartOptions::Kill | RestartOptions::KillOnExit = restart_option {
+        let triggers = !success || matches!(restart_option, RestartOptions::KillOnExit);
+        // a command that was killed on its own (see CommandSet::remove) doesn't take the others down
+        if triggers && !kill_trigger.is_killed() {
+            let _ = kill_trigger.initiate_kill_all();
+        }
+        return false;
+    }
+
+    if kill_trigger.is_killed() {
+        return false;
+    }
+
+    let (attempt, delay) = match restarts
+        .as_mut()
+        .and_then(|tracker| tracker.next_restart(success, uptime))
+    {
+        Some(next) => next,
+        None => return false,
+    };
+
+    send_chan.send(OutputMessagePayload::Restarting {
+        attempt,
+        delay,
+        changed: None,
+    });
+
+    // a kill during the delay cancels the restart
+    matches!(kill_trigger.wait_timeout(delay), Ok(false))
+}
+
+/// Starts watching the files of `command`, if it has any patterns (see [Command::watch](crate::Command::watch)).
+/// Errors are reported on the channel, in which case the command runs without watching.
+fn start_watcher(
+    command: &InnerCommand,
+    send_chan: &message::MessageSender,
+    options: &Options,
+) -> Option<watch::Watcher> {
+    // stages of a pipeline are never restarted
+    if command.watch.is_empty() || command.pipeline.is_some() {
+        return None;
+    }
+
+    match watch::Watcher::start(
+        &command.watch,
+        command.cur_dir.as_deref(),
+        options.watch_debounce,
+    ) {
+        Ok(watcher) => Some(watcher),
+        Err(e) => {
+            send_chan.send(OutputMessagePayload::Error(e));
+            None
+        }
+    }
 }
 
-fn kill_thread(kill_trigger: &kill_barrier::KillBarrier, child: Arc<Mutex<process::Child>>) {
-    let _ = kill_trigger.wait();
+/// Waits until the files watched by a command change and settle.
+/// Returns true if the command should be spawned again, in which case the restart has already been announced on the channel.
+/// Returns false right away if the command doesn't watch any files, and once the kill switch is thrown otherwise.
+fn reload_on_change(
+    watcher: Option<&watch::Watcher>,
+    send_chan: &message::MessageSender,
+    kill_trigger: &kill_barrier::KillBarrier,
+) -> bool {
+    match watcher.and_then(|watcher| watcher.wait_for_changes(kill_trigger)) {
+        Some(changed) => {
+            send_chan.send(OutputMessagePayload::Restarting {
+                attempt: 0,
+                delay: Duration::ZERO,
+                changed: Some(changed),
+            });
+            true
+        }
+        None => false,
+    }
+}
 
-    let lock_res = child.lock();
-    if let Ok(mut locked_child) = lock_res {
-        let _ = locked_child.kill();
+/// Spawns the child process for `command`.
+/// If the command sets its own `PATH` and the executable can't be found there, the [CommandError] is returned inside the `io::Error`.
+/// When the command merges its output, both standard out and standard error are attached to the write end
+/// of a single pipe and the read end is returned alongside the child.
+fn spawn_process(
+    command: &InnerCommand,
+    stage: &mut pipeline::StageIo,
+    options: &Options,
+) -> io::Result<(process::Child, Option<io::PipeReader>)> {
+    let program = command.resolve().map_err(|e| match e {
+        CommandError::NotExecutable(_) => io::Error::new(io::ErrorKind::PermissionDenied, e),
+        _ => io::Error::new(io::ErrorKind::NotFound, e),
+    })?;
+    let mut command_process = command.clone().into_process(program);
+    match stage.stdin.take() {
+        Some(pipe) => command_process.stdin(pipe),
+        None => command_process.stdin(command.stdin.to_stdio(command.cur_dir.as_deref())?),
+    };
+    if options.process_groups {
+        command_process.process_group(0);
+    }
+    if options.kill_on_parent_death {
+        kill_on_parent_death(&mut command_process);
+    }
+    let mut merged_output = None;
+    if let Some(pipe) = stage.stdout.take() {
+        if command.is_output_merged() {
+            command_process.stderr(pipe.try_clone()?);
+        }
+        command_process.stdout(pipe);
+    } else if command.is_output_merged() {
+        let (reader, writer) = io::pipe()?;
+        command_process.stdout(writer.try_clone()?);
+        command_process.stderr(writer);
+        merged_output = Some(reader);
+    }
+
+    let child = command_process.spawn()?;
+    // The process::Command holds on to its copy of the pipe's write end.
+    // It needs to be dropped here or the reader never sees end of file.
+    drop(command_process);
+    Ok((child, merged_output))
+}
+
+/// LINUX-ONLY: Has the kernel send `SIGKILL` to the child once the thread that spawned it exits.
+/// Commands are spawned (and respawned) by their command thread, which only exits after the command is done for good,
+/// so the signal only fires when the whole process goes down.
+#[cfg(target_os = "linux")]
+fn kill_on_parent_death(command_process: &mut process::Command) {
+    let parent = process::id() as libc::pid_t;
+    unsafe {
+        command_process.pre_exec(move || {
+            if libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            // The parent could have died before the death signal was set up, in which case it will never fire
+            if libc::getppid() != parent {
+                return Err(io::Error::from_raw_os_error(libc::ESRCH));
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn kill_on_parent_death(_command_process: &mut process::Command) {}
+
+/// LINUX-ONLY: Marks the current process as a child subreaper so that orphaned descendants of the commands are
+/// re-parented to it instead of `init`.
+#[cfg(target_os = "linux")]
+fn become_child_subreaper() -> io::Result<()> {
+    if unsafe { libc::prctl(libc::PR_SET_CHILD_SUBREAPER, 1) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn become_child_subreaper() -> io::Result<()> {
+    Ok(())
+}
+
+/// Waits until `child` needs to be stopped, either because the kill switch was thrown, because `deadline` passed
+/// or because the files watched by the `watcher` changed.
+/// Returns true if the deadline passed. Returns early once the child exits on its own so that no thread is left behind
+/// for every run of a command, in which case stopping it afterwards is a no-op.
+fn kill_thread(
+    kill_trigger: &kill_barrier::KillBarrier,
+    child: &child::SharedChild,
+    deadline: Option<Instant>,
+    watcher: Option<&watch::Watcher>,
+) -> bool {
+    loop {
+        if watcher
+            .map(|watcher| watcher.has_settled_changes())
+            .unwrap_or(false)
+        {
+            return false;
+        }
+
+        let mut poll_interval = KILL_THREAD_POLL_INTERVAL;
+        if let Some(deadline) = deadline {
+            let now = Instant::now();
+            if now >= deadline {
+                return !child.has_exited();
+            }
+            poll_interval = poll_interval.min(deadline - now);
+        }
+
+        match kill_trigger.wait_timeout(poll_interval) {
+            Ok(true) | Err(_) => return false,
+            Ok(false) if child.has_exited() => return false,
+            Ok(false) => {}
+        }
     }
 }
 
 fn read_stream<R>(
-    cmd_name: &str,
-    send_chan: mpsc::Sender<OutputMessage>,
+    send_chan: message::MessageSender,
     reader: &mut R,
     is_stdout: bool,
+    notifier: &readiness::ReadyNotifier,
 ) where
     R: BufRead,
 {
@@ -194,23 +871,21 @@ fn read_stream<R>(
         let line = line_parse::get_line(reader);
         match line {
             Ok(Some(line_vec)) => {
-                let _ = send_chan.send(OutputMessage {
-                    name: cmd_name.to_string(),
-                    message: if is_stdout {
-                        OutputMessagePayload::Stdout(line_vec.0, line_vec.1)
-                    } else {
-                        OutputMessagePayload::Stderr(line_vec.0, line_vec.1)
-                    },
+                let is_ready_line = notifier.matches_line(&line_vec.1);
+                send_chan.send(if is_stdout {
+                    OutputMessagePayload::Stdout(line_vec.0, line_vec.1)
+                } else {
+                    OutputMessagePayload::Stderr(line_vec.0, line_vec.1)
                 });
+                if is_ready_line {
+                    notifier.notify();
+                }
             }
             Ok(None) => {
                 return;
             }
             Err(e) => {
-                let _ = send_chan.send(OutputMessage {
-                    name: cmd_name.to_string(),
-                    message: OutputMessagePayload::Error(e),
-                });
+                send_chan.send(OutputMessagePayload::Error(e));
             }
         }
     }
@@ -0,0 +1,238 @@
+use super::kill_barrier::KillBarrier;
+use std::cmp::Reverse;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// How often a command waiting for a slot re-checks whether it has been killed
+const KILL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+struct SlotState {
+    limit: Option<usize>,
+    running: usize,
+    /// The commands waiting for a slot, in the order they get one: highest priority first, then in the order they were added
+    waiting: BTreeSet<(Reverse<i32>, usize)>,
+    /// How many stages of every pipeline hold the slot their pipeline shares
+    pipelines: HashMap<usize, usize>,
+}
+
+/// Limits how many commands run at the same time (see [Runner::max_parallel](crate::Runner::max_parallel))
+#[derive(Clone)]
+pub struct Slots {
+    state: Arc<(Mutex<SlotState>, Condvar)>,
+}
+
+/// The place of a single command in the queue
+pub struct Ticket {
+    slots: Slots,
+    key: (Reverse<i32>, usize),
+    pipeline: Option<usize>,
+}
+
+/// A taken slot. Dropping it frees the slot for the next command in the queue
+/// (once every stage that shares it is done, for the stages of a pipeline)
+pub struct SlotGuard {
+    slots: Slots,
+    pipeline: Option<usize>,
+}
+
+impl Slots {
+    /// Creates a queue that lets `limit` commands run at the same time, or any number of commands if `None`
+    pub fn new(limit: Option<usize>) -> Self {
+        Slots {
+            state: Arc::new((
+                Mutex::new(SlotState {
+                    limit: limit.map(|limit| limit.max(1)),
+                    running: 0,
+                    waiting: BTreeSet::new(),
+                    pipelines: HashMap::new(),
+                }),
+                Condvar::new(),
+            )),
+        }
+    }
+
+    /// Creates the ticket of the command at `index` (in the order of the runner) with the given `priority`.
+    /// The stages of a `pipeline` share a single slot: they can only make progress together, since a stage blocks once
+    /// the pipe to the next one is full, so giving them a slot each could take up every slot with blocked stages.
+    pub fn ticket(&self, priority: i32, index: usize, pipeline: Option<usize>) -> Ticket {
+        Ticket {
+            slots: self.clone(),
+            key: (Reverse(priority), index),
+            pipeline,
+        }
+    }
+}
+
+impl Ticket {
+    /// Puts the command in line without waiting, so that commands which are known upfront are started in
+    /// the order of their priority rather than the order their threads happen to get going
+    pub fn enqueue(&self) {
+        let (lock, _) = &*self.slots.state;
+        if let Ok(mut state) = lock.lock() {
+            if state.limit.is_some() {
+                state.waiting.insert(self.key);
+            }
+        }
+    }
+
+    /// Blocks until a slot is free and it is the turn of this command.
+    /// `on_queued` is called if the command can't get a slot right away.
+    /// Returns `Ok(None)` if the kill switch was thrown while waiting. A command can get in line again once it has dropped its slot.
+    pub fn acquire<F: FnOnce()>(
+        &self,
+        kill_trigger: &KillBarrier,
+        on_queued: F,
+    ) -> Result<Option<SlotGuard>, String> {
+        let key = self.key;
+        let (lock, cvar) = &*self.slots.state;
+        let mut state = lock.lock().map_err(|e| e.to_string())?;
+        if state.limit.is_none() {
+            return Ok(Some(self.take(&mut state)));
+        }
+        state.waiting.insert(key);
+
+        let mut on_queued = Some(on_queued);
+        loop {
+            let has_slot = match state.limit {
+                Some(limit) => state.running < limit,
+                None => true,
+            };
+            // another stage of the pipeline already holds the slot
+            let shares_slot = self
+                .pipeline
+                .is_some_and(|pipeline| state.pipelines.contains_key(&pipeline));
+            if shares_slot || (has_slot && state.waiting.first() == Some(&key)) {
+                state.waiting.remove(&key);
+                let guard = self.take(&mut state);
+                // the next command in line may be able to take a slot as well
+                cvar.notify_all();
+                return Ok(Some(guard));
+            }
+
+            if kill_trigger.is_killed() {
+                state.waiting.remove(&key);
+                cvar.notify_all();
+                return Ok(None);
+            }
+
+            if let Some(on_queued) = on_queued.take() {
+                on_queued();
+            }
+
+            state = cvar
+                .wait_timeout(state, KILL_POLL_INTERVAL)
+                .map_err(|e| e.to_string())?
+                .0;
+        }
+    }
+
+    /// Takes a slot, or joins the slot of the pipeline if another stage holds it already
+    fn take(&self, state: &mut SlotState) -> SlotGuard {
+        match self.pipeline {
+            Some(pipeline) => {
+                let holders = state.pipelines.entry(pipeline).or_insert(0);
+                if *holders == 0 {
+                    state.running += 1;
+                }
+                *holders += 1;
+            }
+            None => state.running += 1,
+        }
+        SlotGuard {
+            slots: self.slots.clone(),
+            pipeline: self.pipeline,
+        }
+    }
+}
+
+impl Drop for SlotGuard {
+    fn drop(&mut self) {
+        let (lock, cvar) = &*self.slots.state;
+        if let Ok(mut state) = lock.lock() {
+            if let Some(pipeline) = self.pipeline {
+                let holders = state.pipelines.entry(pipeline).or_insert(1);
+                *holders -= 1;
+                if *holders > 0 {
+                    return;
+                }
+                state.pipelines.remove(&pipeline);
+            }
+            state.running -= 1;
+            cvar.notify_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ChannelCommand;
+    use crate::Command;
+    use crate::OutputMessagePayload;
+    use crate::Runner;
+    use crate::CURRENT_WORKING_DIRECTORY;
+
+    #[test]
+    fn limits_parallel_commands() {
+        let mut runner = Runner::new();
+        for name in ["a", "b", "c", "d", "e"] {
+            runner.command(
+                ChannelCommand::from_string(name, "sleep 0.2", CURRENT_WORKING_DIRECTORY).unwrap(),
+            );
+        }
+        let handle = runner.max_parallel(2).execute();
+
+        let mut messages: Vec<_> = handle.get_output_channel().iter().collect();
+        handle.join().unwrap();
+        messages.sort_by_key(|msg| msg.elapsed);
+
+        let mut running = 0;
+        let mut max_running = 0;
+        let mut queued = 0;
+        for msg in messages {
+            match msg.message {
+                OutputMessagePayload::Start => running += 1,
+                OutputMessagePayload::Done(_) => running -= 1,
+                OutputMessagePayload::Queued => queued += 1,
+                _ => {}
+            }
+            max_running = max_running.max(running);
+        }
+        assert_eq!(max_running, 2);
+        assert_eq!(queued, 3);
+    }
+
+    #[test]
+    fn starts_by_priority() {
+        let handle = Runner::new()
+            .command(ChannelCommand::from_string("a", "true", CURRENT_WORKING_DIRECTORY).unwrap())
+            .command(
+                ChannelCommand::from_string("b", "true", CURRENT_WORKING_DIRECTORY)
+                    .unwrap()
+                    .priority(5),
+            )
+            .command(
+                ChannelCommand::from_string("c", "true", CURRENT_WORKING_DIRECTORY)
+                    .unwrap()
+                    .priority(1),
+            )
+            .max_parallel(1)
+            .execute();
+
+        let mut started = Vec::new();
+        let mut queued = Vec::new();
+        for msg in &handle {
+            match msg.message {
+                OutputMessagePayload::Start => started.push(msg.name),
+                OutputMessagePayload::Queued => queued.push(msg.name),
+                _ => {}
+            }
+        }
+        handle.join().unwrap();
+        queued.sort();
+
+        assert_eq!(started, vec!["b", "c", "a"]);
+        assert_eq!(queued, vec!["a", "c"]);
+    }
+}
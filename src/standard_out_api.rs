@@ -2,6 +2,8 @@ use crate::template::TemplateStrings;
 
 use super::color;
 use super::color::Color;
+use super::grouping;
+use super::grouping::OutputGrouping;
 use super::template;
 use super::Command;
 use super::CommandError;
@@ -10,8 +12,8 @@ use super::InnerCommand;
 use super::OutputMessagePayload;
 use super::Runner;
 use std::collections::HashMap;
-use std::io::Write;
 use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
 use std::thread;
 
 /// Represents a command that prints all messages to the console.
@@ -71,6 +73,14 @@ impl Command for ConsoleCommand {
     fn get_command_mut(&mut self) -> &mut InnerCommand {
         &mut self.inner_command
     }
+
+    fn get_color(&self) -> Option<Color> {
+        Some(self.color.clone())
+    }
+
+    fn set_color(&mut self, color: Color) {
+        self.color(color);
+    }
 }
 
 impl AsRef<ConsoleCommand> for ConsoleCommand {
@@ -81,20 +91,20 @@ impl AsRef<ConsoleCommand> for ConsoleCommand {
 
 pub fn run_commands_stdout(runner: &Runner<ConsoleCommand>) -> ControlledCommandHandle {
     let mut name_color_hash = HashMap::new();
-    let mut inner_commands = Vec::new();
-    let mut num_cmds = 0;
     let options = runner.to_options();
 
     for cmd in &runner.commands {
         name_color_hash.insert(cmd.inner_command.name.to_string(), cmd.color.clone());
-        inner_commands.push(cmd.inner_command.clone());
-        num_cmds += 1;
     }
 
     color::populate_random_colors(&mut name_color_hash);
+    // shared with the handle, which adds the colors of commands added later on
+    let colors = Arc::new(Mutex::new(name_color_hash));
+    let colors_clone = colors.clone();
 
     let quiet = options.quiet;
     let file_handle_flags = options.file_handle_flags;
+    let output_grouping = options.output_grouping;
 
     let handle = super::run_commands(runner);
 
@@ -105,10 +115,10 @@ pub fn run_commands_stdout(runner: &Runner<ConsoleCommand>) -> ControlledCommand
     let supervisor = thread::spawn(move || {
         process_channel(
             &recv,
-            &name_color_hash,
-            num_cmds,
+            &colors_clone,
             quiet,
             file_handle_flags,
+            output_grouping,
             template_strings,
         );
     });
@@ -117,100 +127,110 @@ pub fn run_commands_stdout(runner: &Runner<ConsoleCommand>) -> ControlledCommand
         handle: handle.handle,
         kill_trigger: handle.kill_trigger,
         pids: handle.pids,
+        tracker: handle.tracker,
+        commands: handle.commands,
+        exit_policy: handle.exit_policy,
+        colors: Some(colors),
     }
 }
 
 fn process_channel(
     chan: &mpsc::Receiver<super::OutputMessage>,
-    color_map: &HashMap<String, Color>,
-    num_cmds: usize,
+    color_map: &Mutex<HashMap<String, Color>>,
     quiet: bool,
     file_handle_flags: bool,
+    output_grouping: OutputGrouping,
     template_strings: TemplateStrings,
 ) {
-    loop {
-        let message = chan.recv();
-        if message.is_err() {
-            return;
-        }
-
-        let message = message.unwrap();
-        let output_color = color_map.get(&message.name).unwrap();
-        let color_open_sequence = color::open_sequence(output_color);
-        let mut template = template::Template::new(Some(output_color));
-        template.name = message.name.clone();
+    let mut stdout = std::io::stdout();
+    let mut grouper = grouping::Grouper::new(output_grouping);
+    while let Some(message) = grouper.recv(chan, &mut stdout) {
+        let (output_color, num_cmds) = match color_map.lock() {
+            Ok(color_map) => (
+                color_map.get(&message.name).cloned().unwrap_or_default(),
+                color_map.len(),
+            ),
+            Err(_) => (Color::Default, 0),
+        };
+        let color_open_sequence = color::open_sequence(&output_color);
+        let mut template = template::Template::new(Some(&output_color));
+        template.stamp(&message);
+        let boundary = grouping::Boundary::of(&message.message);
         let color_reset_sequence = color::close_sequence();
         let std_out_flag = if file_handle_flags { " (o)" } else { "" };
         let std_err_flag = if file_handle_flags { " (e)" } else { "" };
-        let mut stdout = std::io::stdout();
-        let _ = stdout.write_all(color_open_sequence.as_bytes());
-        let _ = match message.message {
+        let mut output = color_open_sequence.into_bytes();
+        match message.message {
             OutputMessagePayload::Start => {
                 if !quiet {
                     let template_string =
                         template.execute(&template_strings.start_message_template);
-                    stdout.write_all(
+                    output.extend_from_slice(
                         format!("{}{}\n", template_string, color_reset_sequence).as_bytes(),
-                    )
-                } else {
-                    Ok(())
+                    );
                 }
             }
+            OutputMessagePayload::Ready
+            | OutputMessagePayload::Queued
+            | OutputMessagePayload::Stopped(_)
+            | OutputMessagePayload::Restarting { .. }
+            | OutputMessagePayload::TimedOut => {}
             OutputMessagePayload::Done(exit_status) => {
                 if !quiet {
-                    template.status_code = exit_status;
+                    template.exit = Some(exit_status);
                     let template_string = template.execute(&template_strings.done_message_template);
-                    stdout.write_all(
+                    output.extend_from_slice(
                         format!("{}{}\n", template_string, color_reset_sequence).as_bytes(),
-                    )
-                } else {
-                    Ok(())
+                    );
                 }
             }
             OutputMessagePayload::Stdout(ending, mut bytes) => {
                 template.handle_flag = std_out_flag.to_string();
-                let mut prefix = format!(
-                    "{}{} ",
-                    template.execute(&template_strings.payload_message_template),
-                    color_reset_sequence
-                )
-                .into_bytes();
-                prefix.append(&mut bytes);
+                output.extend_from_slice(
+                    format!(
+                        "{}{} ",
+                        template.execute(&template_strings.payload_message_template),
+                        color_reset_sequence
+                    )
+                    .as_bytes(),
+                );
+                output.append(&mut bytes);
                 if num_cmds == 1 && ending.is_carriage_return() {
-                    prefix.push(b'\r');
+                    output.push(b'\r');
                 } else {
-                    prefix.push(b'\n');
+                    output.push(b'\n');
                 }
-                stdout.write_all(&prefix)
             }
             OutputMessagePayload::Stderr(ending, mut bytes) => {
                 template.handle_flag = std_err_flag.to_string();
-                let mut prefix = format!(
-                    "{}{} ",
-                    template.execute(&template_strings.payload_message_template),
-                    color_reset_sequence
-                )
-                .into_bytes();
-                prefix.append(&mut bytes);
+                output.extend_from_slice(
+                    format!(
+                        "{}{} ",
+                        template.execute(&template_strings.payload_message_template),
+                        color_reset_sequence
+                    )
+                    .as_bytes(),
+                );
+                output.append(&mut bytes);
                 if num_cmds == 1 && ending.is_carriage_return() {
-                    prefix.push(b'\r');
+                    output.push(b'\r');
                 } else {
-                    prefix.push(b'\n');
+                    output.push(b'\n');
                 }
-                stdout.write_all(&prefix)
             }
             OutputMessagePayload::Error(e) => {
                 template.error_message = e.to_string();
-                stdout.write_all(
+                output.extend_from_slice(
                     format!(
                         "{}{}\n",
                         template.execute(&template_strings.error_message_template),
                         color_reset_sequence
                     )
                     .as_bytes(),
-                )
+                );
             }
         };
+        grouper.write(&mut stdout, &message.name, boundary, &output);
     }
 }
 
@@ -0,0 +1,269 @@
+use super::control::PidEntry;
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags};
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+
+/// How often a write to a [CommandStdin] that waits for the command to read checks whether the pipe has been closed
+const STDIN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Describes how a child's standard out or standard error handle is set up.
+/// Set per command via [Command::stdout](crate::Command::stdout) and [Command::stderr](crate::Command::stderr).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum StdioMode {
+    /// (DEFAULT) Capture the stream and pass each line along as an
+    /// [OutputMessagePayload::Stdout](crate::OutputMessagePayload::Stdout) or [OutputMessagePayload::Stderr](crate::OutputMessagePayload::Stderr) message.
+    #[default]
+    Piped,
+    /// The child writes directly to the parent's handle. Output bypasses currant entirely (no prefixes, colors or writers).
+    Inherit,
+    /// Discard the stream.
+    Null,
+    /// Send standard out and standard error into one shared pipe so that lines arrive in the order the child wrote them.
+    /// Setting either handle to `Merged` merges both of them, which overrides the mode of the other handle:
+    /// `stdout(StdioMode::Null)` combined with `stderr(StdioMode::Merged)` still captures both streams, and so does `Inherit`.
+    /// Merged lines are reported as [OutputMessagePayload::Stdout](crate::OutputMessagePayload::Stdout).
+    Merged,
+}
+
+impl StdioMode {
+    /// Returns true if and only if `self` is [StdioMode::Merged]
+    pub fn is_merged(&self) -> bool {
+        matches!(self, StdioMode::Merged)
+    }
+
+    /// Converts the mode to a std `Stdio`.
+    /// `Merged` can't be expressed for a single handle so it falls back to a regular pipe.
+    pub(crate) fn to_stdio(self) -> Stdio {
+        match self {
+            StdioMode::Piped | StdioMode::Merged => Stdio::piped(),
+            StdioMode::Inherit => Stdio::inherit(),
+            StdioMode::Null => Stdio::null(),
+        }
+    }
+}
+
+/// Describes how a child's standard in handle is set up. Set per command via [Command::stdin](crate::Command::stdin).
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub enum StdinMode {
+    /// (DEFAULT) The child reads from the parent's standard in. When several commands inherit it, they compete for the input.
+    #[default]
+    Inherit,
+    /// The child reads end of file right away.
+    Null,
+    /// The child reads the given file. Relative paths are resolved against the directory of the command.
+    /// If the file can't be opened, the run fails with an [OutputMessagePayload::Error](crate::OutputMessagePayload::Error).
+    File(PathBuf),
+    /// The child reads the given bytes followed by end of file. Every run of the command gets the full buffer again.
+    Bytes(Vec<u8>),
+    /// The child reads whatever is written to the [CommandStdin] returned by [CommandHandle::stdin](crate::CommandHandle::stdin).
+    Interactive,
+}
+
+impl StdinMode {
+    /// Converts the mode to a std `Stdio`, opening the file relative to `dir` if there is one
+    pub(crate) fn to_stdio(&self, dir: Option<&Path>) -> io::Result<Stdio> {
+        match self {
+            StdinMode::Inherit => Ok(Stdio::inherit()),
+            StdinMode::Null => Ok(Stdio::null()),
+            StdinMode::File(path) => {
+                let path = match dir {
+                    Some(dir) => dir.join(path),
+                    None => path.clone(),
+                };
+                Ok(File::open(path)?.into())
+            }
+            StdinMode::Bytes(_) | StdinMode::Interactive => Ok(Stdio::piped()),
+        }
+    }
+}
+
+/// A writer that feeds standard in of a command started with [StdinMode::Interactive].
+/// Created by [CommandHandle::stdin](crate::CommandHandle::stdin).
+///
+/// Writes go to the current run of the command, so the writer keeps working across restarts. While the command isn't
+/// running, or after [CommandStdin::close], writes fail with [io::ErrorKind::BrokenPipe].
+/// ## Example
+/// ```
+/// use currant::{ChannelCommand, Command, OutputMessagePayload, Runner, StdinMode, CURRENT_WORKING_DIRECTORY};
+/// use std::io::Write;
+///
+/// let handle = Runner::new()
+///     .command(
+///         ChannelCommand::from_string("repl", "cat", CURRENT_WORKING_DIRECTORY)
+///             .unwrap()
+///             .stdin(StdinMode::Interactive),
+///     )
+///     .execute();
+///
+/// handle.wait_until_ready("repl").unwrap();
+/// let mut stdin = handle.stdin("repl").unwrap();
+/// stdin.write_all(b"hello\n").unwrap();
+/// stdin.close();
+///
+/// for msg in &handle {
+///     if let OutputMessagePayload::Stdout(_, bytes) = msg.message {
+///         assert_eq!(bytes, b"hello");
+///     }
+/// }
+/// handle.join().unwrap();
+/// ```
+pub struct CommandStdin {
+    entry: PidEntry,
+}
+
+impl CommandStdin {
+    pub(crate) fn new(entry: PidEntry) -> Self {
+        CommandStdin { entry }
+    }
+
+    /// Closes standard in of the current run of the command, so that it reads end of file.
+    /// Writes that are waiting for the command to read (in other threads, through other writers for the same command) fail with
+    /// [io::ErrorKind::BrokenPipe] shortly after, so they don't keep the pipe open.
+    /// A restarted command gets a new standard in that can be written to again.
+    pub fn close(&self) {
+        if let Ok(mut stdin) = self.entry.stdin.lock() {
+            stdin.take();
+        }
+    }
+}
+
+impl Write for CommandStdin {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        loop {
+            // the lock is only held to grab the pipe so that a blocked write doesn't hold up the command thread
+            let stdin = match self.entry.stdin.lock() {
+                Ok(stdin) => stdin.clone(),
+                Err(_) => None,
+            };
+            let stdin = match stdin {
+                Some(stdin) => stdin,
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::BrokenPipe,
+                        format!("standard in of '{}' isn't open", self.entry.name),
+                    ))
+                }
+            };
+
+            // Wait for room in the pipe in short steps and look the pipe up again in between, so that a pipe that was closed
+            // in the meantime isn't held open by this write. Once there is room, a write of at most `PIPE_BUF` bytes doesn't block.
+            let mut fds = [PollFd::new(stdin.as_raw_fd(), PollFlags::POLLOUT)];
+            match poll(&mut fds, STDIN_POLL_INTERVAL.as_millis() as i32) {
+                Ok(0) | Err(Errno::EINTR) => continue,
+                Ok(_) => return (&*stdin).write(&buf[..buf.len().min(libc::PIPE_BUF)]),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StdinMode;
+    use crate::ChannelCommand;
+    use crate::Command;
+    use crate::OutputMessagePayload;
+    use crate::Runner;
+    use crate::CURRENT_WORKING_DIRECTORY;
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+
+    #[test]
+    fn feeds_stdin() {
+        let file = env::temp_dir().join(format!("currant_stdin_{}", std::process::id()));
+        fs::write(&file, "from file\n").unwrap();
+
+        let mut runner = Runner::new();
+        for (name, mode) in [
+            ("bytes", StdinMode::Bytes(b"from bytes\n".to_vec())),
+            ("file", StdinMode::File(file.clone())),
+            ("null", StdinMode::Null),
+            ("interactive", StdinMode::Interactive),
+        ] {
+            runner.command(
+                ChannelCommand::from_string(name, "cat", CURRENT_WORKING_DIRECTORY)
+                    .unwrap()
+                    .stdin(mode),
+            );
+        }
+        let handle = runner.execute();
+
+        assert!(handle.stdin("bytes").is_err());
+        handle.wait_until_ready("interactive").unwrap();
+        let mut stdin = handle.stdin("interactive").unwrap();
+        stdin.write_all(b"from handle\n").unwrap();
+        stdin.close();
+        assert!(stdin.write_all(b"too late\n").is_err());
+
+        let mut lines: Vec<_> = handle
+            .get_output_channel()
+            .iter()
+            .filter_map(|msg| match msg.message {
+                OutputMessagePayload::Stdout(_, bytes) => {
+                    Some(format!("{}: {}", msg.name, String::from_utf8_lossy(&bytes)))
+                }
+                _ => None,
+            })
+            .collect();
+        handle.join().unwrap();
+        let _ = fs::remove_file(&file);
+        lines.sort();
+
+        assert_eq!(
+            lines,
+            vec![
+                "bytes: from bytes",
+                "file: from file",
+                "interactive: from handle"
+            ]
+        );
+    }
+
+    #[test]
+    fn close_ends_blocked_writes() {
+        let handle = Runner::new()
+            .command(
+                ChannelCommand::from_argv(
+                    "slow",
+                    "sh",
+                    ["-c", "sleep 1; cat > /dev/null; echo eof"],
+                    CURRENT_WORKING_DIRECTORY,
+                )
+                .unwrap()
+                .stdin(StdinMode::Interactive),
+            )
+            .execute();
+
+        handle.wait_until_ready("slow").unwrap();
+        let mut stdin = handle.stdin("slow").unwrap();
+        // more than fits into the pipe, so the write blocks until the command reads
+        let writer = std::thread::spawn(move || stdin.write_all(&vec![b'x'; 1 << 20]));
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        handle.stdin("slow").unwrap().close();
+
+        let result = writer.join().unwrap();
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::BrokenPipe);
+        let lines: Vec<_> = handle
+            .get_output_channel()
+            .iter()
+            .filter_map(|msg| match msg.message {
+                OutputMessagePayload::Stdout(_, bytes) => Some(bytes),
+                _ => None,
+            })
+            .collect();
+        assert!(handle.join().unwrap()[0].status.unwrap().success());
+        assert_eq!(lines, vec![b"eof".to_vec()]);
+    }
+}
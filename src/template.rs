@@ -1,4 +1,10 @@
 use crate::color;
+use crate::exit;
+use crate::exit::ProcessExit;
+use crate::time;
+use crate::OutputMessage;
+use std::time::Duration;
+use std::time::SystemTime;
 
 pub struct TemplateStrings {
     pub start_message_template: String,
@@ -12,8 +18,11 @@ pub struct Template {
     pub begin_color: String,
     pub reset_color: String,
     pub error_message: String,
-    pub status_code: Option<i32>,
+    pub exit: Option<ProcessExit>,
     pub handle_flag: String,
+    pub time: Option<SystemTime>,
+    pub elapsed: Option<Duration>,
+    pub pid: Option<u32>,
 }
 
 impl Template {
@@ -31,23 +40,61 @@ impl Template {
                 String::new()
             },
             error_message: String::new(),
-            status_code: None,
+            exit: None,
             handle_flag: String::new(),
+            time: None,
+            elapsed: None,
+            pid: None,
         }
     }
 
+    /// Fills in the name, capture time and pid of `message`
+    pub fn stamp(&mut self, message: &OutputMessage) {
+        self.name = message.name.clone();
+        self.time = Some(message.time);
+        self.elapsed = Some(message.elapsed);
+        self.pid = message.pid;
+    }
+
     pub fn execute(&self, template_string: &str) -> String {
-        let status_code_message = if self.status_code.is_some() {
-            format!("{}", self.status_code.unwrap())
+        let status_code_message = if let Some(status_code) = self.exit.and_then(|e| e.code()) {
+            format!("{}", status_code)
+        } else {
+            "(none)".to_string()
+        };
+        let signal_message = if let Some(signal) = self.exit.and_then(|e| e.signal()) {
+            exit::signal_name(signal)
         } else {
             "(none)".to_string()
         };
+        let exit_message = if let Some(exit) = self.exit {
+            exit.to_string()
+        } else {
+            "(none)".to_string()
+        };
+        let time_message = match self.time {
+            Some(time) => time::rfc3339(time),
+            None => "(none)".to_string(),
+        };
+        let elapsed_message = match self.elapsed {
+            Some(elapsed) => time::format_elapsed(elapsed),
+            None => "(none)".to_string(),
+        };
+        let pid_message = match self.pid {
+            Some(pid) => pid.to_string(),
+            None => "(none)".to_string(),
+        };
         template_string
             .replace("{{name}}", &self.name)
+            .replace("{{time}}", &time_message)
+            .replace("{{elapsed}}", &elapsed_message)
+            .replace("{{pid}}", &pid_message)
             .replace("{{begin_color}}", &self.begin_color)
             .replace("{{reset_color}}", &self.reset_color)
             .replace("{{error_message}}", &self.error_message)
             .replace("{{status_code}}", &status_code_message)
+            .replace("{{signal}}", &signal_message)
+            .replace("{{exit}}", &exit_message)
             .replace("{{handle_flag}}", &self.handle_flag)
     }
 }
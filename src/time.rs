@@ -0,0 +1,171 @@
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+const SECS_PER_DAY: u64 = 86400;
+
+/// Formats `time` as an RFC 3339 timestamp in UTC with millisecond precision, like `2024-01-01T12:30:00.000Z`
+pub fn rfc3339(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((secs / SECS_PER_DAY) as i64);
+    let secs_of_day = secs % SECS_PER_DAY;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// Parses the timestamps written by [rfc3339]. Fractional seconds are optional.
+/// Returns `None` for anything else, including other time zones, leap seconds, years outside of `0000` to `9999`,
+/// fields out of range (like a 30th of February) and times before the unix epoch
+#[cfg(feature = "json")]
+pub fn parse_rfc3339(timestamp: &str) -> Option<SystemTime> {
+    let timestamp = timestamp.strip_suffix('Z')?;
+    let (date, time) = timestamp.split_once('T')?;
+
+    let mut date_parts = date.splitn(3, '-');
+    let year: i64 = date_parts.next()?.parse().ok()?;
+    let month: u32 = date_parts.next()?.parse().ok()?;
+    let day: u32 = date_parts.next()?.parse().ok()?;
+    if !(0..=9999).contains(&year) || !(1..=12).contains(&month) {
+        return None;
+    }
+    if day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+
+    let (time, fraction) = match time.split_once('.') {
+        Some((time, fraction)) => (time, fraction),
+        None => (time, ""),
+    };
+    let mut time_parts = time.splitn(3, ':');
+    let hours: u64 = time_parts.next()?.parse().ok()?;
+    let minutes: u64 = time_parts.next()?.parse().ok()?;
+    let secs: u64 = time_parts.next()?.parse().ok()?;
+    if hours >= 24 || minutes >= 60 || secs >= 60 {
+        return None;
+    }
+    let nanos = if fraction.is_empty() {
+        0
+    } else {
+        if fraction.len() > 9 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let digits: String = fraction
+            .chars()
+            .chain("000000000".chars())
+            .take(9)
+            .collect();
+        digits.parse().ok()?
+    };
+
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    let secs = days
+        .checked_mul(SECS_PER_DAY)?
+        .checked_add(hours * 3600 + minutes * 60 + secs)?;
+    UNIX_EPOCH.checked_add(Duration::new(secs, nanos))
+}
+
+/// The number of days in `month` (1 to 12) of `year` in the proleptic Gregorian calendar
+#[cfg(feature = "json")]
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Formats a duration as seconds with millisecond precision, like `12.345s`
+pub fn format_elapsed(elapsed: Duration) -> String {
+    format!("{}.{:03}s", elapsed.as_secs(), elapsed.subsec_millis())
+}
+
+/// Converts days since the unix epoch into a (year, month, day) date of the proleptic Gregorian calendar.
+/// See <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// The inverse of [civil_from_days].
+/// See <http://howardhinnant.github.io/date_algorithms.html#days_from_civil>
+#[cfg(feature = "json")]
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = month as i64;
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_elapsed;
+    use super::rfc3339;
+    use std::time::Duration;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn formats_timestamps() {
+        assert_eq!(rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        assert_eq!(
+            rfc3339(UNIX_EPOCH + Duration::from_millis(1_709_210_096_789)),
+            "2024-02-29T12:34:56.789Z"
+        );
+        assert_eq!(format_elapsed(Duration::from_millis(12_345)), "12.345s");
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn parses_timestamps() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_709_210_096_789);
+        assert_eq!(super::parse_rfc3339(&rfc3339(time)), Some(time));
+        assert_eq!(
+            super::parse_rfc3339("1970-01-02T00:00:00Z"),
+            Some(UNIX_EPOCH + Duration::from_secs(86400))
+        );
+        assert_eq!(super::parse_rfc3339("1970-01-02T00:00:00+01:00"), None);
+        assert_eq!(
+            super::parse_rfc3339("2024-02-29T23:59:59.5Z"),
+            Some(UNIX_EPOCH + Duration::from_millis(1_709_251_199_500))
+        );
+
+        for invalid in [
+            "2023-02-29T00:00:00Z",
+            "2024-13-01T00:00:00Z",
+            "2024-00-01T00:00:00Z",
+            "2024-04-31T00:00:00Z",
+            "2024-01-00T00:00:00Z",
+            "2024-01-01T24:00:00Z",
+            "2024-01-01T00:60:00Z",
+            "2024-01-01T00:00:60Z",
+            "2024-01-01T00:00:00.+5Z",
+            "2024-01-01T00:00:00.1234567890Z",
+            "1969-12-31T23:59:59Z",
+            "99999999999999-01-01T00:00:00Z",
+            "2024-01-01T00:00:18446744073709551615Z",
+        ] {
+            assert_eq!(super::parse_rfc3339(invalid), None, "{}", invalid);
+        }
+    }
+}
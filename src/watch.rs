@@ -0,0 +1,506 @@
+use super::kill_barrier::KillBarrier;
+use super::CommandError;
+use nix::errno::Errno;
+use nix::poll::{poll, PollFd, PollFlags};
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, WatchDescriptor};
+use regex::Regex;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+/// How often the watcher thread checks whether it should stop
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A glob pattern of files to watch. Set via [Command::watch](crate::Command::watch).
+///
+/// `*` matches any part of a file or directory name, `?` a single character, `[abc]` (or `[!abc]`) a character class,
+/// `{a,b}` either alternative and `**` any number of directories, so `src/**/*.rs` matches every Rust file below `src`.
+/// Relative patterns are resolved against the command's directory.
+#[derive(Clone, Debug)]
+pub struct WatchPattern {
+    pattern: String,
+    /// The leading directories of the pattern without any wildcards. Only this directory is watched
+    root: PathBuf,
+    /// Matches paths relative to `root`
+    regex: Regex,
+    /// How many directories deep below `root` a match can be. `None` if the pattern contains `**`
+    depth: Option<usize>,
+}
+
+impl WatchPattern {
+    /// Parses a glob pattern.
+    /// Returns [CommandError::InvalidPattern] if the pattern is empty or a character class or alternative isn't closed.
+    pub fn new<S: AsRef<str>>(pattern: S) -> Result<Self, CommandError> {
+        let pattern = pattern.as_ref();
+        let invalid = |reason: &str| {
+            CommandError::InvalidPattern(format!("invalid glob '{}': {}", pattern, reason))
+        };
+        if pattern.is_empty() {
+            return Err(invalid("empty pattern"));
+        }
+
+        let mut components: Vec<&str> = pattern.split('/').collect();
+        let last = components.pop().unwrap_or_default();
+        let literal = components
+            .iter()
+            .take_while(|component| !component.contains(['*', '?', '[', '{']))
+            .count();
+        let mut root = PathBuf::from(if pattern.starts_with('/') { "/" } else { "" });
+        root.extend(components[..literal].iter().filter(|c| !c.is_empty()));
+        let rest = components[literal..]
+            .iter()
+            .chain(Some(&last))
+            .copied()
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let depth = if rest.contains("**") {
+            None
+        } else {
+            Some(rest.matches('/').count())
+        };
+        let regex = Regex::new(&format!("^{}$", glob_to_regex(&rest).map_err(invalid)?))
+            .map_err(|e| invalid(&e.to_string()))?;
+
+        Ok(WatchPattern {
+            pattern: pattern.to_string(),
+            root,
+            regex,
+            depth,
+        })
+    }
+
+    /// The pattern as it was given
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Returns true if `path` (below `root`, which is the root of the pattern resolved against the command's directory) matches
+    fn matches(&self, root: &Path, path: &Path) -> bool {
+        match path.strip_prefix(root) {
+            Ok(relative) => self.regex.is_match(&relative.to_string_lossy()),
+            Err(_) => false,
+        }
+    }
+}
+
+/// Translates the wildcards of a glob into a regular expression
+fn glob_to_regex(glob: &str) -> Result<String, &'static str> {
+    let mut regex = String::new();
+    let mut chars = glob.chars().peekable();
+    let mut in_alternative = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                regex.push('[');
+                if chars.peek() == Some(&'!') {
+                    chars.next();
+                    regex.push('^');
+                }
+                loop {
+                    match chars.next() {
+                        Some(']') => break,
+                        Some('\\') => regex.push_str("\\\\"),
+                        Some('[') => regex.push_str("\\["),
+                        Some(c) => regex.push(c),
+                        None => return Err("unclosed character class"),
+                    }
+                }
+                regex.push(']');
+            }
+            '{' if !in_alternative => {
+                in_alternative = true;
+                regex.push_str("(?:");
+            }
+            ',' if in_alternative => regex.push('|'),
+            '}' if in_alternative => {
+                in_alternative = false;
+                regex.push(')');
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+
+    if in_alternative {
+        return Err("unclosed alternative");
+    }
+    Ok(regex)
+}
+
+struct WatchState {
+    changed: BTreeSet<PathBuf>,
+    last_change: Option<Instant>,
+}
+
+/// Watches the files of a single command with inotify and collects the paths that changed.
+/// Changes count once no further change arrived for the debounce interval, so that saving many files at once
+/// (or a file being written in several steps) only restarts the command once. Dropping the watcher stops its thread.
+pub struct Watcher {
+    state: Arc<Mutex<WatchState>>,
+    debounce: Duration,
+    stopped: Arc<AtomicBool>,
+}
+
+impl Watcher {
+    /// Starts watching the files matching `patterns`, resolved against `cur_dir` (the current directory if `None`)
+    pub fn start(
+        patterns: &[WatchPattern],
+        cur_dir: Option<&Path>,
+        debounce: Duration,
+    ) -> io::Result<Self> {
+        let inotify = Inotify::init(InitFlags::IN_CLOEXEC | InitFlags::IN_NONBLOCK)?;
+        let base = cur_dir.map(Path::to_path_buf).unwrap_or_default();
+        let patterns: Vec<(PathBuf, WatchPattern)> = patterns
+            .iter()
+            .map(|pattern| (base.join(&pattern.root), pattern.clone()))
+            .collect();
+
+        // every watched directory with the depth left for the directories inside it (`None` if they are too deep to be watched)
+        let mut dirs = HashMap::new();
+        for (root, pattern) in &patterns {
+            if let Err(e) = add_tree(inotify, &mut dirs, root, pattern.depth) {
+                close(inotify);
+                return Err(e);
+            }
+        }
+
+        let watcher = Watcher {
+            state: Arc::new(Mutex::new(WatchState {
+                changed: BTreeSet::new(),
+                last_change: None,
+            })),
+            debounce,
+            stopped: Arc::new(AtomicBool::new(false)),
+        };
+
+        let state = watcher.state.clone();
+        let stopped = watcher.stopped.clone();
+        thread::spawn(move || {
+            while !stopped.load(Ordering::SeqCst) {
+                let mut fds = [PollFd::new(inotify.as_raw_fd(), PollFlags::POLLIN)];
+                match poll(&mut fds, WATCH_POLL_INTERVAL.as_millis() as i32) {
+                    Ok(0) | Err(Errno::EINTR) => continue,
+                    Ok(_) => {}
+                    Err(_) => break,
+                }
+
+                let events = match inotify.read_events() {
+                    Ok(events) => events,
+                    Err(Errno::EAGAIN) => continue,
+                    Err(_) => break,
+                };
+                let mut changed = Vec::new();
+                for event in events {
+                    if event.mask.contains(AddWatchFlags::IN_IGNORED) {
+                        dirs.remove(&event.wd);
+                        continue;
+                    }
+                    let path = match (dirs.get(&event.wd), &event.name) {
+                        (Some((dir, _)), Some(name)) => dir.join(name),
+                        _ => continue,
+                    };
+                    let is_new_dir = event.mask.contains(AddWatchFlags::IN_ISDIR)
+                        && event
+                            .mask
+                            .intersects(AddWatchFlags::IN_CREATE | AddWatchFlags::IN_MOVED_TO);
+                    if is_new_dir {
+                        if let Some(depth) = dirs[&event.wd].1 {
+                            let _ = add_tree(inotify, &mut dirs, &path, depth);
+                        }
+                    }
+                    if patterns
+                        .iter()
+                        .any(|(root, pattern)| pattern.matches(root, &path))
+                    {
+                        changed.push(path);
+                    }
+                }
+
+                if !changed.is_empty() {
+                    if let Ok(mut state) = state.lock() {
+                        state.changed.extend(changed);
+                        state.last_change = Some(Instant::now());
+                    }
+                }
+            }
+            close(inotify);
+        });
+
+        Ok(watcher)
+    }
+
+    /// Returns true if files changed, whether or not the changes have settled yet
+    pub fn has_changes(&self) -> bool {
+        match self.state.lock() {
+            Ok(state) => !state.changed.is_empty(),
+            Err(_) => false,
+        }
+    }
+
+    /// Returns true if files changed and no further change arrived for the debounce interval
+    pub fn has_settled_changes(&self) -> bool {
+        match self.state.lock() {
+            Ok(state) => self.settled(&state),
+            Err(_) => false,
+        }
+    }
+
+    /// Blocks until the changes have settled and returns the paths that changed, in order.
+    /// Returns `None` if the kill switch is thrown first.
+    pub fn wait_for_changes(&self, kill_trigger: &KillBarrier) -> Option<Vec<PathBuf>> {
+        loop {
+            if kill_trigger.is_killed() {
+                return None;
+            }
+            {
+                let mut state = self.state.lock().ok()?;
+                if self.settled(&state) {
+                    state.last_change = None;
+                    return Some(std::mem::take(&mut state.changed).into_iter().collect());
+                }
+            }
+
+            if kill_trigger.wait_timeout(WATCH_POLL_INTERVAL).is_err() {
+                return None;
+            }
+        }
+    }
+
+    fn settled(&self, state: &WatchState) -> bool {
+        match state.last_change {
+            Some(last_change) => {
+                !state.changed.is_empty() && last_change.elapsed() >= self.debounce
+            }
+            None => false,
+        }
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Watches `dir` and, up to `depth` levels deep (no limit if `None`), the directories below it
+fn add_tree(
+    inotify: Inotify,
+    dirs: &mut HashMap<WatchDescriptor, (PathBuf, Option<Option<usize>>)>,
+    dir: &Path,
+    depth: Option<usize>,
+) -> io::Result<()> {
+    let flags = AddWatchFlags::IN_MODIFY
+        | AddWatchFlags::IN_CLOSE_WRITE
+        | AddWatchFlags::IN_ATTRIB
+        | AddWatchFlags::IN_CREATE
+        | AddWatchFlags::IN_DELETE
+        | AddWatchFlags::IN_MOVE
+        | AddWatchFlags::IN_ONLYDIR;
+    let watched = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let wd = inotify.add_watch(watched, flags).map_err(|e| {
+        io::Error::new(
+            io::Error::from(e).kind(),
+            format!("{}: {}", dir.display(), e),
+        )
+    })?;
+
+    let below = match depth {
+        Some(0) => None,
+        Some(depth) => Some(Some(depth - 1)),
+        None => Some(None),
+    };
+    dirs.insert(wd, (dir.to_path_buf(), below));
+
+    if let Some(depth) = below {
+        for entry in fs::read_dir(watched)?.flatten() {
+            if entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+                let _ = add_tree(inotify, dirs, &dir.join(entry.file_name()), depth);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn close(inotify: Inotify) {
+    let _ = nix::unistd::close(inotify.as_raw_fd());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WatchPattern;
+    use crate::ChannelCommand;
+    use crate::Command;
+    use crate::OutputMessagePayload;
+    use crate::Runner;
+    use std::fs;
+    use std::path::Path;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    #[test]
+    fn parses_globs() {
+        let pattern = WatchPattern::new("src/**/*.{rs,toml}").unwrap();
+        assert_eq!(pattern.root, PathBuf::from("src"));
+        assert_eq!(pattern.depth, None);
+        let root = Path::new("app/src");
+        assert!(pattern.matches(root, Path::new("app/src/main.rs")));
+        assert!(pattern.matches(root, Path::new("app/src/bin/cli.toml")));
+        assert!(!pattern.matches(root, Path::new("app/src/main.rs.bak")));
+        assert!(!pattern.matches(root, Path::new("app/tests/main.rs")));
+
+        let pattern = WatchPattern::new("/etc/app/*/[!.]?.conf").unwrap();
+        assert_eq!(pattern.root, PathBuf::from("/etc/app"));
+        assert_eq!(pattern.depth, Some(1));
+        let root = Path::new("/etc/app");
+        assert!(pattern.matches(root, Path::new("/etc/app/site/ab.conf")));
+        assert!(!pattern.matches(root, Path::new("/etc/app/site/.b.conf")));
+        assert!(!pattern.matches(root, Path::new("/etc/app/ab.conf")));
+
+        assert_eq!(
+            WatchPattern::new("Cargo.toml").unwrap().root,
+            PathBuf::new()
+        );
+        assert!(WatchPattern::new("").is_err());
+        assert!(WatchPattern::new("src/[ab.rs").is_err());
+        assert!(WatchPattern::new("src/{a,b.rs").is_err());
+    }
+
+    #[test]
+    fn restarts_on_change() {
+        let dir = std::env::temp_dir().join(format!("currant-watch-{}", std::process::id()));
+        fs::create_dir_all(dir.join("src")).unwrap();
+
+        let handle = Runner::new()
+            .command(
+                ChannelCommand::from_argv(
+                    "server",
+                    "sh",
+                    ["-c", "echo run; exec sleep 10"],
+                    Some(dir.to_string_lossy().to_string()),
+                )
+                .unwrap()
+                .watch(WatchPattern::new("src/*.txt").unwrap()),
+            )
+            .watch_debounce(Duration::from_millis(50))
+            .execute();
+
+        let mut runs = 0;
+        let mut changes = Vec::new();
+        for msg in &handle {
+            match msg.message {
+                OutputMessagePayload::Stdout(..) => {
+                    runs += 1;
+                    if runs == 1 {
+                        fs::write(dir.join("src/ignored.log"), "").unwrap();
+                        fs::write(dir.join("src/a.txt"), "a").unwrap();
+                    } else {
+                        handle.kill();
+                    }
+                }
+                OutputMessagePayload::Restarting {
+                    attempt,
+                    delay,
+                    changed,
+                } => changes.push((attempt, delay, changed)),
+                _ => {}
+            }
+        }
+        let results = handle.join().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(runs, 2);
+        assert_eq!(
+            changes,
+            vec![(0, Duration::ZERO, Some(vec![dir.join("src/a.txt")]))]
+        );
+        assert!(!results[0].status.unwrap().success());
+    }
+
+    #[test]
+    fn waits_after_exit() {
+        let dir = std::env::temp_dir().join(format!("currant-watch-exit-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let build = || {
+            ChannelCommand::from_string(
+                "build",
+                "echo build",
+                Some(dir.to_string_lossy().to_string()),
+            )
+            .unwrap()
+            .watch(WatchPattern::new("*.txt").unwrap())
+            .clone()
+        };
+
+        // by default, a watched command that is done on its own is done for good
+        let results = Runner::new().command(build()).execute().join().unwrap();
+        assert!(results[0].status.unwrap().success());
+
+        let handle = Runner::new()
+            .command(build())
+            .command(
+                ChannelCommand::from_string(
+                    "other",
+                    "echo other",
+                    crate::CURRENT_WORKING_DIRECTORY,
+                )
+                .unwrap(),
+            )
+            .max_parallel(1)
+            .watch_debounce(Duration::from_millis(50))
+            .watch_after_exit(true)
+            .execute();
+
+        let mut lines = Vec::new();
+        let mut reloads = 0;
+        for msg in &handle {
+            match msg.message {
+                OutputMessagePayload::Stdout(_, bytes) => {
+                    lines.push(String::from_utf8(bytes).unwrap());
+                    // the waiting build freed its slot for the other command
+                    if lines.len() == 2 {
+                        fs::write(dir.join("a.txt"), "a").unwrap();
+                    }
+                }
+                OutputMessagePayload::Done(_) if msg.name == "build" && lines.len() == 3 => {
+                    handle.kill()
+                }
+                OutputMessagePayload::Restarting {
+                    changed: Some(_), ..
+                } => reloads += 1,
+                _ => {}
+            }
+        }
+        let results = handle.join().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(lines, vec!["build", "other", "build"]);
+        assert_eq!(reloads, 1);
+        assert!(results
+            .iter()
+            .all(|result| result.status.unwrap().success()));
+    }
+}
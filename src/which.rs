@@ -1,21 +1,138 @@
-use std::io::ErrorKind;
+use super::CommandError;
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
 use std::path::PathBuf;
-use std::process::Command;
 
-pub fn exec_exists(exec_name: &str, dir: &Option<PathBuf>) -> bool {
-    let mut command = Command::new(exec_name);
+/// Finds the executable that running `exec_name` in `dir` would start, without running it.
+/// Names containing a `/` are paths and are resolved against `dir` (or the current directory). Other names are looked up
+/// in every directory of `path_var` (the `PATH` of the current process if `None`), where relative directories are resolved against `dir` as well.
+/// Returns the absolute path of the executable, [CommandError::CommandNotFound] if there is no such file and
+/// [CommandError::NotExecutable] if the only matches aren't executable.
+pub fn resolve(
+    exec_name: &str,
+    dir: &Option<PathBuf>,
+    path_var: Option<&OsStr>,
+) -> Result<PathBuf, CommandError> {
+    if exec_name.contains('/') {
+        let candidate = absolute(Path::new(exec_name), dir);
+        return match check_executable(&candidate) {
+            Some(true) => Ok(candidate),
+            Some(false) => Err(CommandError::NotExecutable(candidate.display().to_string())),
+            None => Err(CommandError::CommandNotFound(exec_name.to_string())),
+        };
+    }
+
+    let path_var = match path_var {
+        Some(path_var) => Some(path_var.to_os_string()),
+        None => env::var_os("PATH"),
+    };
+
+    let mut not_executable = None;
+    for search_dir in env::split_paths(&path_var.unwrap_or_default()) {
+        let candidate = absolute(&search_dir.join(exec_name), dir);
+        match check_executable(&candidate) {
+            Some(true) => return Ok(candidate),
+            Some(false) if not_executable.is_none() => not_executable = Some(candidate),
+            _ => {}
+        }
+    }
+
+    match not_executable {
+        Some(candidate) => Err(CommandError::NotExecutable(candidate.display().to_string())),
+        None => Err(CommandError::CommandNotFound(exec_name.to_string())),
+    }
+}
+
+/// Resolves a relative `path` against `dir` and then the current directory
+fn absolute(path: &Path, dir: &Option<PathBuf>) -> PathBuf {
+    let path = match dir {
+        Some(dir) => dir.join(path),
+        None => path.to_path_buf(),
+    };
+
+    if path.is_absolute() {
+        path
+    } else {
+        env::current_dir()
+            .map(|cwd| cwd.join(&path))
+            .unwrap_or(path)
+    }
+}
 
-    if let Some(path) = dir {
-        command.current_dir(path);
+/// Returns `None` if there is no file at `path`, otherwise whether it has any of the execute bits set
+fn check_executable(path: &Path) -> Option<bool> {
+    let metadata = fs::metadata(path).ok()?;
+    if !metadata.is_file() {
+        return None;
+    }
+    Some(metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::resolve;
+    use crate::CommandError;
+    use std::env;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("currant_which_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_file(path: &PathBuf, mode: u32) {
+        fs::write(path, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
     }
 
-    let child = command.spawn();
+    #[test]
+    fn resolves_from_path() {
+        let dir = scratch_dir("path");
+        let first = dir.join("first");
+        let second = dir.join("second");
+        fs::create_dir_all(&first).unwrap();
+        fs::create_dir_all(&second).unwrap();
+        write_file(&first.join("tool"), 0o644);
+        write_file(&second.join("tool"), 0o755);
 
-    match child {
-        Ok(mut c) => {
-            let _ = c.kill();
-            true
+        let path_var = env::join_paths([&first, &second]).unwrap();
+        assert_eq!(
+            resolve("tool", &None, Some(&path_var)).unwrap(),
+            second.join("tool")
+        );
+
+        let path_var = env::join_paths([&first]).unwrap();
+        match resolve("tool", &None, Some(&path_var)) {
+            Err(CommandError::NotExecutable(path)) => {
+                assert_eq!(PathBuf::from(path), first.join("tool"))
+            }
+            _ => panic!("non-executable file wasn't reported"),
+        }
+
+        match resolve("missing", &None, Some(&path_var)) {
+            Err(CommandError::CommandNotFound(name)) => assert_eq!(name, "missing"),
+            _ => panic!("missing command wasn't reported"),
         }
-        Err(e) => !matches!(e.kind(), ErrorKind::NotFound),
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn resolves_relative_to_dir() {
+        let dir = scratch_dir("relative");
+        write_file(&dir.join("run.sh"), 0o755);
+
+        assert_eq!(
+            resolve("./run.sh", &Some(dir.clone()), None).unwrap(),
+            dir.join("./run.sh")
+        );
+        assert!(resolve("./run.sh", &None, None).is_err());
+        let _ = fs::remove_dir_all(&dir);
     }
 }
@@ -1,3 +1,5 @@
+use super::grouping;
+use super::grouping::OutputGrouping;
 use super::template;
 use super::Command;
 use super::ControlledCommandHandle;
@@ -71,6 +73,30 @@ impl AsRef<WriterCommand> for WriterCommand {
     }
 }
 
+/// The format the Writer API writes messages in. Set via [Runner::output_format]
+///
+/// More formats are only available with their feature (like `JsonLines` with the `json` feature).
+/// The enum is non-exhaustive so that enabling a feature in one crate of a build can't break a `match` in another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum OutputFormat {
+    /// (DEFAULT) Human readable text following the message templates of the [Runner]
+    #[default]
+    Text,
+    /// One JSON object per line and event, for log aggregators. Requires the `json` feature.
+    ///
+    /// Every object has the fields `ts` (the capture time as an RFC 3339 timestamp in UTC), `elapsed_ms`, `seq`, `name`, `event` (`queued`, `start`,
+    /// `ready`, `output`, `exit`, `error`, `stopped`, `restarting` or `timed_out`) and, when known, `pid`.
+    /// See [OutputMessage](crate::OutputMessage) for the meaning of the fields. Output events add `stream` (`stdout` or `stderr`),
+    /// `line` (lossy UTF-8), `line_ending` and, if the line isn't valid UTF-8, `line_base64` with the raw bytes.
+    /// Exit events add `exit` with either `code`, `signal` and `core_dumped`, or `stopped`. Error events add `error`.
+    /// Stopped events add `stop` (the stop signal or `killed`), restarting events add `attempt`, `delay_ms` and, for restarts because of changed files, `changed`.
+    ///
+    /// The templates and [OutputGrouping] don't apply to this format. Use [JsonLinesReader](crate::JsonLinesReader) to parse the output back into [OutputMessage](crate::OutputMessage)s.
+    #[cfg(feature = "json")]
+    JsonLines,
+}
+
 pub fn run_commands_writer<W>(runner: &Runner<WriterCommand>, writer: W) -> ControlledCommandHandle
 where
     W: Write + Send + 'static,
@@ -80,82 +106,121 @@ where
     let recv = handle.channel;
 
     let template_strings = runner.get_template_strings();
+    let options = runner.to_options();
+    let file_handle_flags = options.file_handle_flags;
+    let output_grouping = options.output_grouping;
+    let output_format = runner.output_format;
 
-    let supervisor = thread::spawn(move || {
-        process_channel(&recv, template_strings, writer);
+    let supervisor = thread::spawn(move || match output_format {
+        #[cfg(feature = "json")]
+        OutputFormat::JsonLines => super::json::process_channel(&recv, writer),
+        OutputFormat::Text => process_channel(
+            &recv,
+            template_strings,
+            file_handle_flags,
+            output_grouping,
+            writer,
+        ),
     });
     ControlledCommandHandle {
         supervisor,
         handle: handle.handle,
         kill_trigger: handle.kill_trigger,
         pids: handle.pids,
+        tracker: handle.tracker,
+        commands: handle.commands,
+        exit_policy: handle.exit_policy,
+        colors: None,
     }
 }
 
 fn process_channel<W>(
     chan: &mpsc::Receiver<super::OutputMessage>,
     template_strings: template::TemplateStrings,
+    file_handle_flags: bool,
+    output_grouping: OutputGrouping,
     mut writer: W,
 ) where
     W: Write + Send,
 {
-    loop {
-        let message = chan.recv();
-        if message.is_err() {
-            return;
+    let mut grouper = grouping::Grouper::new(output_grouping);
+    while let Some(message) = grouper.recv(chan, &mut writer) {
+        let boundary = grouping::Boundary::of(&message.message);
+        let output = format_text(&message, &template_strings, file_handle_flags);
+        grouper.write(&mut writer, &message.name, boundary, &output);
+    }
+}
+
+/// Formats `message` following the message templates, without colors.
+/// Messages that have no text (like [OutputMessagePayload::Ready]) format to an empty buffer.
+pub(crate) fn format_text(
+    message: &super::OutputMessage,
+    template_strings: &template::TemplateStrings,
+    file_handle_flags: bool,
+) -> Vec<u8> {
+    let mut template = template::Template::new(None);
+    template.stamp(message);
+
+    match &message.message {
+        OutputMessagePayload::Start => format!(
+            "{}\n",
+            template.execute(&template_strings.start_message_template)
+        )
+        .into_bytes(),
+        OutputMessagePayload::Ready
+        | OutputMessagePayload::Queued
+        | OutputMessagePayload::Stopped(_)
+        | OutputMessagePayload::Restarting { .. }
+        | OutputMessagePayload::TimedOut => Vec::new(),
+        OutputMessagePayload::Done(exit_status) => {
+            template.exit = Some(*exit_status);
+            format!(
+                "{}\n",
+                template.execute(&template_strings.done_message_template)
+            )
+            .into_bytes()
+        }
+        OutputMessagePayload::Stdout(_, bytes) => {
+            template.handle_flag = if file_handle_flags { " (o)" } else { "" }.to_string();
+            let mut output = template
+                .execute(&template_strings.payload_message_template)
+                .into_bytes();
+            output.extend_from_slice(bytes);
+            output.push(b'\n');
+            output
         }
+        OutputMessagePayload::Stderr(_, bytes) => {
+            template.handle_flag = if file_handle_flags { " (e)" } else { "" }.to_string();
+            let mut output = template
+                .execute(&template_strings.payload_message_template)
+                .into_bytes();
+            output.extend_from_slice(bytes);
+            output.push(b'\n');
+            output
+        }
+        OutputMessagePayload::Error(e) => {
+            template.error_message = e.to_string();
+            format!(
+                "{}\n",
+                template.execute(&template_strings.error_message_template)
+            )
+            .into_bytes()
+        }
+    }
+}
+
+/// A writer that keeps everything written to it in memory, shared by all of its clones. Used by the tests of the Writer API.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub(crate) struct SharedBuffer(pub(crate) std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
 
-        let message = message.unwrap();
-
-        let mut template = template::Template::new(None);
-        template.name = message.name;
-
-        let _ = match message.message {
-            OutputMessagePayload::Start => writer.write_all(
-                format!(
-                    "{}\n",
-                    template.execute(&template_strings.start_message_template)
-                )
-                .as_bytes(),
-            ),
-            OutputMessagePayload::Done(exit_status) => {
-                template.status_code = exit_status;
-                writer.write_all(
-                    format!(
-                        "{}\n",
-                        template.execute(&template_strings.done_message_template)
-                    )
-                    .as_bytes(),
-                )
-            }
-            OutputMessagePayload::Stdout(_, mut bytes) => {
-                template.handle_flag = " (o)".to_string();
-                let mut prefix = template
-                    .execute(&template_strings.payload_message_template)
-                    .into_bytes();
-                prefix.append(&mut bytes);
-                prefix.push(b'\n');
-                writer.write_all(&prefix)
-            }
-            OutputMessagePayload::Stderr(_, mut bytes) => {
-                template.handle_flag = " (e)".to_string();
-                let mut prefix = template
-                    .execute(&template_strings.payload_message_template)
-                    .into_bytes();
-                prefix.append(&mut bytes);
-                prefix.push(b'\n');
-                writer.write_all(&prefix)
-            }
-            OutputMessagePayload::Error(e) => {
-                template.error_message = e.to_string();
-                writer.write_all(
-                    format!(
-                        "{}\n",
-                        template.execute(&template_strings.error_message_template)
-                    )
-                    .as_bytes(),
-                )
-            }
-        };
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
     }
 }